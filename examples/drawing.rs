@@ -96,6 +96,30 @@ impl event::EventHandler for MainState {
                           32)?;
 
         graphics::circle(ctx, DrawMode::Fill, Point { x: 600.0, y: 380.0 }, 40.0, 32)?;
+        graphics::circle(ctx, DrawMode::Line, Point { x: 600.0, y: 480.0 }, 40.0, 32)?;
+
+        graphics::arc(ctx,
+                      DrawMode::Fill,
+                      graphics::ArcMode::Pie,
+                      Point { x: 700.0, y: 100.0 },
+                      40.0,
+                      0.0,
+                      4.0,
+                      24)?;
+
+        let rect = graphics::Rect::new(700.0, 300.0, 80.0, 50.0);
+        graphics::rounded_rectangle(ctx, DrawMode::Line, rect, 10.0)?;
+
+        graphics::set_stroke_options(ctx,
+                                     graphics::StrokeOptions {
+                                         width: 2.0,
+                                         cap: graphics::LineCap::Round,
+                                         dashes: vec![8.0, 6.0],
+                                         ..Default::default()
+                                     });
+        let rect = graphics::Rect::new(700.0, 450.0, 80.0, 80.0);
+        graphics::rectangle(ctx, DrawMode::Line, rect)?;
+        graphics::set_stroke_options(ctx, graphics::StrokeOptions::default());
 
         graphics::present(ctx);
         Ok(())
@@ -16,6 +16,7 @@ struct MainState {
     a: i32,
     direction: i32,
     image: graphics::Image,
+    font: graphics::Font,
     text: graphics::Text,
     bmptext: graphics::Text,
     // Not actually dead, see BUGGO below
@@ -53,7 +54,26 @@ impl MainState {
         let image = graphics::Image::new(ctx, "/dragon1.png").unwrap();
 
         let font = graphics::Font::new(ctx, "/DejaVuSerif.ttf", 48).unwrap();
-        let text = graphics::Text::new(ctx, "Hello world!", &font).unwrap();
+        // An outline and shadow keep the text readable over the image.
+        let options = graphics::TextOptions {
+            outline: Some(graphics::Outline {
+                              width: 2.0,
+                              color: graphics::BLACK,
+                          }),
+            shadow: Some(graphics::Shadow {
+                             offset: graphics::Point::new(3.0, 3.0),
+                             color: Color::new(0.0, 0.0, 0.0, 0.5),
+                             blur: 2.0,
+                         }),
+            ..Default::default()
+        };
+        let text = graphics::Text::new_with_options(ctx,
+                                                    &["Hello world!".into()],
+                                                    &font,
+                                                    None,
+                                                    graphics::Align::Left,
+                                                    &options)
+            .unwrap();
         let bmpfont = graphics::Font::new_bitmap(ctx, "/arial.png", "ABCDEFGHIJKLMNOPQRSTUVWXYZ")
             .unwrap();
         let bmptext = graphics::Text::new(ctx, "ZYXWVYTSRQPONMLKJIHGFEDCBA", &bmpfont).unwrap();
@@ -65,6 +85,7 @@ impl MainState {
             a: 0,
             direction: 1,
             image: image,
+            font: font,
             text: text,
             bmptext: bmptext,
             // BUGGO: We never use sound again,
@@ -105,6 +126,16 @@ impl event::EventHandler for MainState {
         graphics::draw(ctx, &self.bmptext, dest_point, 0.0)?;
 
         self.draw_crazy_lines(ctx)?;
+
+        // Text that changes every frame is best drawn through the glyph cache.
+        let fps = format!("FPS: {:.0}", timer::get_fps(ctx));
+        graphics::queue_text(ctx,
+                             &[fps.into()],
+                             &self.font,
+                             graphics::Point::new(10.0, 500.0),
+                             None,
+                             graphics::Align::Left)?;
+        graphics::draw_queued_text(ctx, graphics::DrawParam::default())?;
         graphics::present(ctx);
 
         timer::sleep_until_next_frame(ctx, 60);
@@ -0,0 +1,71 @@
+//! A very simple shader example.
+
+#[macro_use]
+extern crate gfx;
+extern crate ggez;
+
+use ggez::*;
+use ggez::graphics::{DrawMode, Point};
+use std::time::Duration;
+
+gfx_defines!{
+    constant Dim {
+        rate: f32 = "u_Rate",
+    }
+}
+
+struct MainState {
+    dim: Dim,
+    shader: graphics::Shader<Dim>,
+}
+
+impl MainState {
+    fn new(ctx: &mut Context) -> GameResult<MainState> {
+        let dim = Dim { rate: 0.5 };
+        // The built-in vertex shader does everything we need,
+        // so we use it as it is.
+        let vertex_source = include_bytes!("../src/graphics/shader/basic_150.glslv");
+        let pixel_source = include_bytes!("../resources/dimmer_150.glslf");
+        let shader = graphics::Shader::from_u8(ctx, vertex_source, pixel_source, dim, "Dim")?;
+        Ok(MainState {
+               dim: dim,
+               shader: shader,
+           })
+    }
+}
+
+impl event::EventHandler for MainState {
+    fn update(&mut self, ctx: &mut Context, _dt: Duration) -> GameResult<()> {
+        let seconds = timer::duration_to_f64(timer::get_time_since_start(ctx)) as f32;
+        self.dim.rate = 0.5 + (seconds.cos() / 2.0);
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        graphics::clear(ctx);
+
+        graphics::circle(ctx, DrawMode::Fill, Point::new(100.0, 300.0), 100.0, 32)?;
+
+        self.shader.send(ctx, self.dim)?;
+        {
+            let _lock = graphics::use_shader(ctx, &self.shader);
+            graphics::circle(ctx, DrawMode::Fill, Point::new(400.0, 300.0), 100.0, 32)?;
+        }
+
+        graphics::circle(ctx, DrawMode::Fill, Point::new(700.0, 300.0), 100.0, 32)?;
+
+        graphics::present(ctx);
+        Ok(())
+    }
+}
+
+pub fn main() {
+    let c = conf::Conf::new();
+    let ctx = &mut Context::load_from_conf("shader", "ggez", c).unwrap();
+    let state = &mut MainState::new(ctx).unwrap();
+    if let Err(e) = event::run(ctx, state) {
+        println!("Error encountered: {}", e);
+    } else {
+        println!("Game exited cleanly.");
+    }
+}
@@ -0,0 +1,66 @@
+extern crate ggez;
+use ggez::*;
+use ggez::graphics::{DrawParam, Point};
+use std::time::Duration;
+
+struct MainState {
+    spritebatch: graphics::SpriteBatch,
+    rotation: f32,
+}
+
+impl MainState {
+    fn new(ctx: &mut Context) -> GameResult<MainState> {
+        let image = graphics::Image::new(ctx, "/tile.png")?;
+        let batch = graphics::SpriteBatch::new(image);
+        let s = MainState {
+            spritebatch: batch,
+            rotation: 0.0,
+        };
+        Ok(s)
+    }
+}
+
+impl event::EventHandler for MainState {
+    fn update(&mut self, _ctx: &mut Context, _dt: Duration) -> GameResult<()> {
+        self.rotation += 0.01;
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
+        graphics::clear(ctx);
+        self.spritebatch.clear();
+        for x in 0..150 {
+            for y in 0..150 {
+                let x = x as f32;
+                let y = y as f32;
+                let p = DrawParam {
+                    dest: Point::new(x * 10.0, y * 10.0),
+                    scale: Point::new(0.25, 0.25),
+                    rotation: self.rotation * (x + y) / 100.0,
+                    ..Default::default()
+                };
+                self.spritebatch.add(p);
+            }
+        }
+        let param = DrawParam {
+            dest: Point::new(400.0, 300.0),
+            rotation: -self.rotation,
+            offset: Point::new(-400.0, -300.0),
+            ..Default::default()
+        };
+        graphics::draw_ex(ctx, &self.spritebatch, param)?;
+        graphics::present(ctx);
+        Ok(())
+    }
+}
+
+pub fn main() {
+    let c = conf::Conf::new();
+    let ctx = &mut Context::load_from_conf("spritebatch", "ggez", c).unwrap();
+    let state = &mut MainState::new(ctx).unwrap();
+    if let Err(e) = event::run(ctx, state) {
+        println!("Error encountered: {}", e);
+    } else {
+        println!("Game exited cleanly.");
+    }
+}
@@ -23,7 +23,8 @@ use GameResult;
 /// You generally don't have to create this yourself, it will be part 
 /// of your `Context` object.
 pub struct AudioContext {
-    endpoint: rodio::Endpoint,
+    /// `None` if this is a null audio context that plays nothing.
+    endpoint: Option<rodio::Endpoint>,
 }
 
 impl AudioContext {
@@ -31,7 +32,13 @@ impl AudioContext {
         let error = GameError::AudioError(String::from("Could not initialize sound system (for \
                                                         some reason)"));
         let e = rodio::get_default_endpoint().ok_or(error)?;
-        Ok(AudioContext { endpoint: e })
+        Ok(AudioContext { endpoint: Some(e) })
+    }
+
+    /// Creates an `AudioContext` that never touches the sound hardware.
+    /// `Source`s created with it can be used as normal but are silent.
+    pub fn new_null() -> AudioContext {
+        AudioContext { endpoint: None }
     }
 }
 
@@ -87,7 +94,8 @@ impl AsRef<[u8]> for SoundData {
 // for now it works.
 pub struct Source {
     data: SoundData,
-    sink: rodio::Sink,
+    /// `None` if the `Source` was created with a null `AudioContext`.
+    sink: Option<rodio::Sink>,
 }
 
 impl Source {
@@ -103,7 +111,10 @@ impl Source {
 
     /// Creates a new Source using the given SoundData object.
     pub fn from_data(context: &mut Context, data: SoundData) -> GameResult<Self> {
-        let sink = rodio::Sink::new(&context.audio_context.endpoint);
+        let sink = context.audio_context
+            .endpoint
+            .as_ref()
+            .map(rodio::Sink::new);
         Ok(Source {
                data: data,
                sink: sink,
@@ -117,32 +128,42 @@ impl Source {
         // redundant, but it's fine for now.
         let cursor = io::Cursor::new(self.data.clone());
         let decoder = rodio::Decoder::new(cursor)?;
-        self.sink.append(decoder);
+        if let Some(ref sink) = self.sink {
+            sink.append(decoder);
+        }
         Ok(())
     }
 
     pub fn pause(&self) {
-        self.sink.pause()
+        if let Some(ref sink) = self.sink {
+            sink.pause()
+        }
     }
     pub fn resume(&self) {
-        self.sink.play()
+        if let Some(ref sink) = self.sink {
+            sink.play()
+        }
     }
 
 
     // pub fn stop(&self) {}
     // pub fn set_looping() {}
     pub fn set_volume(&mut self, value: f32) {
-        self.sink.set_volume(value)
+        if let Some(ref mut sink) = self.sink {
+            sink.set_volume(value)
+        }
     }
 
+    /// Returns the volume of the `Source`; always 0.0 if it
+    /// is silent because of a null `AudioContext`.
     pub fn volume(&self) -> f32 {
-        self.sink.volume()
+        self.sink.as_ref().map(|sink| sink.volume()).unwrap_or(0.0)
     }
     // pub fn stopped(&self) -> bool {
     //     false
     // }
     pub fn paused(&self) -> bool {
-        self.sink.is_paused()
+        self.sink.as_ref().map(|sink| sink.is_paused()).unwrap_or(true)
     }
     pub fn playing(&self) -> bool {
         !self.paused() // && !self.stopped()
@@ -26,6 +26,24 @@ pub struct Conf {
     /// Whether or not the graphics draw rate should be
     /// synchronized with the monitor's draw rate.
     pub vsync: bool,
+    /// Whether to run without showing anything or making any noise.
+    /// The window is created hidden and audio goes to a null backend
+    /// that never opens a sound device, so a `Context` can be created
+    /// for tests or servers.  Tests can then step an `EventHandler`
+    /// by calling its `update()` and `draw()` methods directly
+    /// instead of going through `event::run()`.
+    ///
+    /// An OpenGL context is still needed.  On a machine without a
+    /// display this means either running under a virtual X server
+    /// such as Xvfb, or an SDL build with the "offscreen" video driver
+    /// and a software OpenGL implementation such as Mesa's llvmpipe.
+    /// A headless `Context` sets the `SDL_VIDEODRIVER` environment
+    /// variable to "offscreen" unless it already picks a driver.  The
+    /// offscreen driver needs SDL 2.0.10 or newer; with older SDLs
+    /// creating the `Context` fails, so set `SDL_VIDEODRIVER` to
+    /// another driver yourself, such as "x11" under Xvfb.
+    #[serde(default)]
+    pub headless: bool,
     /* To implement still.
      * window_borderless: bool,
      * window_resizable: bool,
@@ -57,6 +75,7 @@ impl Default for Conf {
     ///     window_height: 600
     ///     window_width: 800
     ///     vsync: true
+    ///     headless: false
     /// }
     /// ```
     fn default() -> Self {
@@ -66,6 +85,7 @@ impl Default for Conf {
             window_height: 600,
             window_width: 800,
             vsync: true,
+            headless: false,
         }
 
     }
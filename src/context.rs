@@ -5,6 +5,7 @@ use sdl2::surface;
 use sdl2::pixels;
 use image::{self, GenericImage};
 
+use std::env;
 use std::fmt;
 use std::io::Read;
 
@@ -37,6 +38,8 @@ pub struct Context {
     pub timer_context: timer::TimeContext,
     pub audio_context: audio::AudioContext,
     pub gamepad_context: input::GamepadContext,
+    pub keyboard_context: input::keyboard::KeyboardContext,
+    pub mouse_context: input::mouse::MouseContext,
 
     pub default_font: graphics::Font,
 }
@@ -75,13 +78,31 @@ fn set_window_icon(context: &mut Context) -> GameResult<()> {
     Ok(())
 }
 
+/// Returns the SDL video driver to ask for, if any.  Headless contexts
+/// use SDL's "offscreen" driver, which needs no display, unless the
+/// `SDL_VIDEODRIVER` environment variable already names a driver.
+///
+/// The driver is asked for by setting `SDL_VIDEODRIVER` for the process,
+/// since only SDL 2.0.22 and newer read it as a hint as well.
+fn video_driver_hint(conf: &conf::Conf, env_driver: Option<&str>) -> Option<&'static str> {
+    match env_driver {
+        Some(driver) if !driver.is_empty() => None,
+        _ if conf.headless => Some("offscreen"),
+        _ => None,
+    }
+}
+
 impl Context {
     /// Tries to create a new Context using settings from the given config file.
     /// Usually called by `Context::load_from_conf()`.
     fn from_conf(conf: conf::Conf, fs: Filesystem, sdl_context: Sdl) -> GameResult<Context> {
         let video = sdl_context.video()?;
 
-        let audio_context = audio::AudioContext::new()?;
+        let audio_context = if conf.headless {
+            audio::AudioContext::new_null()
+        } else {
+            audio::AudioContext::new()?
+        };
         let event_context = sdl_context.event()?;
         let timer_context = timer::TimeContext::new();
         let font = graphics::Font::default_font()?;
@@ -89,7 +110,8 @@ impl Context {
                                                               &conf.window_title,
                                                               conf.window_width,
                                                               conf.window_height,
-                                                              conf.vsync)?;
+                                                              conf.vsync,
+                                                              conf.headless)?;
         let gamepad_context = input::GamepadContext::new(&sdl_context)?;
 
         let mut ctx = Context {
@@ -101,7 +123,9 @@ impl Context {
             timer_context: timer_context,
             audio_context: audio_context,
             gamepad_context: gamepad_context,
-            
+            keyboard_context: input::keyboard::KeyboardContext::new(),
+            mouse_context: input::mouse::MouseContext::new(),
+
             default_font: font,
         };
 
@@ -123,11 +147,16 @@ impl Context {
                           default_config: conf::Conf)
                           -> GameResult<Context> {
 
-        let sdl_context = sdl2::init()?;
         let mut fs = Filesystem::new(game_id, author)?;
 
         let config = fs.read_config().unwrap_or(default_config);
 
+        let env_driver = env::var("SDL_VIDEODRIVER").ok();
+        if let Some(driver) = video_driver_hint(&config, env_driver.as_ref().map(|s| s.as_str())) {
+            env::set_var("SDL_VIDEODRIVER", driver);
+        }
+        let sdl_context = sdl2::init()?;
+
         Context::from_conf(config, fs, sdl_context)
     }
 
@@ -149,3 +178,19 @@ impl Context {
             .map_err(GameError::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_video_driver_hint() {
+        let mut c = conf::Conf::new();
+        assert_eq!(video_driver_hint(&c, None), None);
+        c.headless = true;
+        assert_eq!(video_driver_hint(&c, None), Some("offscreen"));
+        assert_eq!(video_driver_hint(&c, Some("")), Some("offscreen"));
+        // The environment variable wins.
+        assert_eq!(video_driver_hint(&c, Some("x11")), None);
+    }
+}
@@ -169,6 +169,58 @@ impl From<gfx::CombinedError> for GameError {
     }
 }
 
+impl From<gfx::shade::ProgramError> for GameError {
+    fn from(e: gfx::shade::ProgramError) -> GameError {
+        let errstr = format!("Shader compilation error: {:?}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
+impl From<gfx::buffer::CreationError> for GameError {
+    fn from(e: gfx::buffer::CreationError) -> GameError {
+        let errstr = format!("Buffer creation error: {}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
+impl From<gfx::texture::CreationError> for GameError {
+    fn from(e: gfx::texture::CreationError) -> GameError {
+        let errstr = format!("Texture creation error: {}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
+impl From<gfx::ResourceViewError> for GameError {
+    fn from(e: gfx::ResourceViewError) -> GameError {
+        let errstr = format!("Texture resource view error: {}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
+impl From<gfx::TargetViewError> for GameError {
+    fn from(e: gfx::TargetViewError) -> GameError {
+        let errstr = format!("Render target view error: {}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
+impl From<gfx::mapping::Error> for GameError {
+    fn from(e: gfx::mapping::Error) -> GameError {
+        let errstr = format!("Buffer mapping error: {:?}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
+impl<S, T> From<gfx::CopyError<S, T>> for GameError
+    where S: fmt::Debug,
+          T: fmt::Debug
+{
+    fn from(e: gfx::CopyError<S, T>) -> GameError {
+        let errstr = format!("Copy error: {:?}", e);
+        GameError::VideoError(errstr)
+    }
+}
+
 impl<T> From<gfx::UpdateError<T>> for GameError
     where T: fmt::Debug + fmt::Display + 'static
 {
@@ -69,6 +69,19 @@ pub trait EventHandler {
 
     fn key_up_event(&mut self, _keycode: Keycode, _keymod: Mod, _repeat: bool) {}
 
+    /// Called when text is typed, once the keyboard layout and any
+    /// input method have turned key presses into characters.  This,
+    /// rather than `key_down_event()`, is what to use for text boxes.
+    /// It's only called while text input is on; see
+    /// `input::start_text_input()`.
+    fn text_input_event(&mut self, _text: &str) {}
+
+    /// Called while an input method is composing text that hasn't been
+    /// typed yet, with the text so far and the cursor position `start`
+    /// and selection `length` within it, in characters.  The finished
+    /// text arrives through `text_input_event()`.
+    fn text_editing_event(&mut self, _text: &str, _start: i32, _length: i32) {}
+
     fn controller_button_down_event(&mut self, _btn: Button, _instance_id: i32) {}
     fn controller_button_up_event(&mut self, _btn: Button, _instance_id: i32) {}
     fn controller_axis_event(&mut self, _axis: Axis, _value: i16, _instance_id: i32) {}
@@ -97,6 +110,8 @@ pub fn run<S>(ctx: &mut Context, state: &mut S) -> GameResult<()>
         let mut continuing = true;
         while continuing {
             ctx.timer_context.tick();
+            ctx.keyboard_context.tick();
+            ctx.mouse_context.tick();
 
             for event in event_pump.poll_iter() {
                 match event {
@@ -111,6 +126,7 @@ pub fn run<S>(ctx: &mut Context, state: &mut S) -> GameResult<()>
                         ..
                     } => {
                         if let Some(key) = keycode {
+                            ctx.keyboard_context.set_key(key, true, keymod);
                             if key == keyboard::Keycode::Escape {
                                 ctx.quit()?;
                             } else {
@@ -125,13 +141,23 @@ pub fn run<S>(ctx: &mut Context, state: &mut S) -> GameResult<()>
                         ..
                     } => {
                         if let Some(key) = keycode {
+                            ctx.keyboard_context.set_key(key, false, keymod);
                             state.key_up_event(key, keymod, repeat)
                         }
                     }
+                    TextInput { ref text, .. } => state.text_input_event(text),
+                    TextEditing {
+                        ref text,
+                        start,
+                        length,
+                        ..
+                    } => state.text_editing_event(text, start, length),
                     MouseButtonDown { mouse_btn, x, y, .. } => {
+                        ctx.mouse_context.set_button(mouse_btn, true, x, y);
                         state.mouse_button_down_event(mouse_btn, x, y)
                     }
                     MouseButtonUp { mouse_btn, x, y, .. } => {
+                        ctx.mouse_context.set_button(mouse_btn, false, x, y);
                         state.mouse_button_up_event(mouse_btn, x, y)
                     }
                     MouseMotion {
@@ -141,7 +167,10 @@ pub fn run<S>(ctx: &mut Context, state: &mut S) -> GameResult<()>
                         xrel,
                         yrel,
                         ..
-                    } => state.mouse_motion_event(mousestate, x, y, xrel, yrel),
+                    } => {
+                        ctx.mouse_context.set_position(x, y, xrel, yrel);
+                        state.mouse_motion_event(mousestate, x, y, xrel, yrel)
+                    }
                     MouseWheel { x, y, .. } => state.mouse_wheel_event(x, y),
                     ControllerButtonDown { button, which, .. } => {
                         state.controller_button_down_event(button, which)
@@ -0,0 +1,413 @@
+//! Sprite sheets and frame-based animations.
+//!
+//! A `SpriteSheet` is an `Image` cut up into frames, either on a grid
+//! or one rectangle at a time.  An `Animation` steps through a list of
+//! those frames over time; call its `update()` with the `dt` passed to
+//! `EventHandler::update()`, then draw the frame it's on:
+//!
+//! ```rust,ignore
+//! let sheet = graphics::SpriteSheet::from_grid(image, 32, 32)?;
+//! let mut walk = graphics::Animation::new(vec![0, 1, 2, 3],
+//!                                         Duration::from_millis(100),
+//!                                         graphics::AnimationMode::Loop);
+//!
+//! // In update():
+//! walk.update(dt);
+//!
+//! // In draw():
+//! sheet.draw_frame(ctx, walk.current_frame(), graphics::DrawParam {
+//!     dest: player_pos,
+//!     ..Default::default()
+//! })?;
+//! ```
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::*;
+
+/// An `Image` divided up into frames, each identified by
+/// its index and optionally a name.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    image: Image,
+    frames: Vec<Rect>,
+    names: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+    /// Creates a new `SpriteSheet` for the given `Image` with no frames.
+    pub fn new(image: Image) -> Self {
+        SpriteSheet {
+            image: image,
+            frames: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Creates a new `SpriteSheet` by dividing the given `Image` into
+    /// a grid of frames of the given size in pixels.  The frames are
+    /// numbered left to right, then top to bottom; any leftover space
+    /// at the right or bottom edges is ignored.
+    pub fn from_grid(image: Image, frame_width: u32, frame_height: u32) -> GameResult<Self> {
+        if frame_width == 0 || frame_height == 0 || frame_width > image.width() ||
+           frame_height > image.height() {
+            let msg = format!("Can't divide a {}x{} image into frames of size {}x{}",
+                              image.width(),
+                              image.height(),
+                              frame_width,
+                              frame_height);
+            return Err(GameError::RenderError(msg));
+        }
+        let frames = grid_frames(image.width(), image.height(), frame_width, frame_height);
+        Ok(SpriteSheet {
+               image: image,
+               frames: frames,
+               names: HashMap::new(),
+           })
+    }
+
+    /// Adds a frame covering the given rectangle of the image,
+    /// in pixels, and returns its index.
+    pub fn add_frame(&mut self, rect: Rect) -> usize {
+        let (width, height) = (self.image.width() as f32, self.image.height() as f32);
+        self.frames
+            .push(Rect::new(rect.x / width, rect.y / height, rect.w / width, rect.h / height));
+        self.frames.len() - 1
+    }
+
+    /// Adds a frame like `add_frame()`, which can also be looked up
+    /// by name with `frame_index()`.
+    pub fn add_named_frame(&mut self, name: &str, rect: Rect) -> usize {
+        let index = self.add_frame(rect);
+        self.names.insert(name.to_owned(), index);
+        index
+    }
+
+    /// Gives a name to an existing frame, so it can be looked up with
+    /// `frame_index()`.
+    pub fn set_frame_name(&mut self, name: &str, index: usize) -> GameResult<()> {
+        if index >= self.frames.len() {
+            let msg = format!("Frame {} is not in the SpriteSheet", index);
+            return Err(GameError::RenderError(msg));
+        }
+        self.names.insert(name.to_owned(), index);
+        Ok(())
+    }
+
+    /// Returns the index of the frame with the given name, if there is one.
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).cloned()
+    }
+
+    /// Returns the `DrawParam.src` rectangle that draws the given frame,
+    /// if it exists.
+    pub fn frame_src(&self, index: usize) -> Option<Rect> {
+        self.frames.get(index).cloned()
+    }
+
+    /// Returns the number of frames in the sheet.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns true if the sheet has no frames.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns the `Image` the frames are taken from.
+    pub fn get_image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Draws the given frame of the sheet.  The `src` field of
+    /// the `DrawParam` is replaced by the frame's.
+    pub fn draw_frame(&self, ctx: &mut Context, index: usize, param: DrawParam) -> GameResult<()> {
+        match self.frame_src(index) {
+            Some(src) => self.image.draw_ex(ctx, DrawParam { src: src, ..param }),
+            None => {
+                let msg = format!("Frame {} is not in the SpriteSheet", index);
+                Err(GameError::RenderError(msg))
+            }
+        }
+    }
+}
+
+/// Works out the `src` rectangles for a grid of frames of the given
+/// size on an image of the given size.
+fn grid_frames(image_width: u32,
+               image_height: u32,
+               frame_width: u32,
+               frame_height: u32)
+               -> Vec<Rect> {
+    let columns = image_width / frame_width;
+    let rows = image_height / frame_height;
+    let w = frame_width as f32 / image_width as f32;
+    let h = frame_height as f32 / image_height as f32;
+    let mut frames = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            frames.push(Rect::new(column as f32 * w, row as f32 * h, w, h));
+        }
+    }
+    frames
+}
+
+/// What an `Animation` does once it reaches its last frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnimationMode {
+    /// Goes back to the first frame and keeps going.
+    Loop,
+    /// Goes backwards to the first frame, then forwards again, and so on.
+    PingPong,
+    /// Stays on the last frame.
+    Once,
+}
+
+/// A sequence of frames, each shown for some amount of time.
+///
+/// An animation can have tags, which name a range of its frames, such as
+/// "walk" or "jump".  Playing a tag restricts it to those frames.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    frames: Vec<(usize, Duration)>,
+    mode: AnimationMode,
+    tags: HashMap<String, (usize, usize)>,
+    range: (usize, usize),
+    position: usize,
+    elapsed: Duration,
+    forward: bool,
+    finished: bool,
+    paused: bool,
+}
+
+impl Animation {
+    /// Creates a new `Animation` showing the given `SpriteSheet` frames
+    /// in order, each for the same amount of time.
+    pub fn new(frames: Vec<usize>, frame_duration: Duration, mode: AnimationMode) -> Self {
+        let frames = frames.into_iter()
+            .map(|frame| (frame, frame_duration))
+            .collect();
+        Animation::with_durations(frames, mode)
+    }
+
+    /// Creates a new `Animation` from a list of `SpriteSheet` frames
+    /// and how long each one is shown for.
+    pub fn with_durations(frames: Vec<(usize, Duration)>, mode: AnimationMode) -> Self {
+        let len = frames.len();
+        Animation {
+            frames: frames,
+            mode: mode,
+            tags: HashMap::new(),
+            range: (0, len),
+            position: 0,
+            elapsed: Duration::new(0, 0),
+            forward: true,
+            finished: false,
+            paused: false,
+        }
+    }
+
+    /// Advances the animation by the given amount of time.
+    pub fn update(&mut self, dt: Duration) {
+        if self.paused || self.finished || self.range.0 >= self.range.1 {
+            return;
+        }
+        // If every frame takes no time at all, we'd go around forever.
+        let zero = Duration::new(0, 0);
+        if self.frames[self.range.0..self.range.1].iter().all(|&(_, d)| d == zero) {
+            return;
+        }
+        self.elapsed += dt;
+        loop {
+            let duration = self.frames[self.position].1;
+            if self.elapsed < duration {
+                break;
+            }
+            self.elapsed -= duration;
+            self.advance();
+            if self.finished {
+                self.elapsed = zero;
+                break;
+            }
+        }
+    }
+
+    /// Moves on to the next frame, according to the animation mode.
+    fn advance(&mut self) {
+        let (start, end) = self.range;
+        let last = end - 1;
+        match self.mode {
+            AnimationMode::Loop => {
+                self.position = if self.position >= last {
+                    start
+                } else {
+                    self.position + 1
+                };
+            }
+            AnimationMode::Once => {
+                if self.position >= last {
+                    self.finished = true;
+                } else {
+                    self.position += 1;
+                }
+            }
+            AnimationMode::PingPong => {
+                if start == last {
+                    return;
+                }
+                if self.forward && self.position >= last {
+                    self.forward = false;
+                } else if !self.forward && self.position <= start {
+                    self.forward = true;
+                }
+                if self.forward {
+                    self.position += 1;
+                } else {
+                    self.position -= 1;
+                }
+            }
+        }
+    }
+
+    /// Returns the `SpriteSheet` frame the animation is currently on.
+    ///
+    /// Panics if the animation has no frames.
+    pub fn current_frame(&self) -> usize {
+        self.frames[self.position].0
+    }
+
+    /// Returns the `DrawParam.src` rectangle for the current frame in the
+    /// given `SpriteSheet`, or the whole image if the sheet has no such frame.
+    pub fn src(&self, sheet: &SpriteSheet) -> Rect {
+        sheet.frame_src(self.current_frame())
+            .unwrap_or_else(|| Rect::new(0.0, 0.0, 1.0, 1.0))
+    }
+
+    /// Names the range of frames from `start` up to but not including
+    /// `end`, by their position in the animation, so it can be played
+    /// with `play_tag()`.
+    pub fn add_tag(&mut self, name: &str, start: usize, end: usize) -> GameResult<()> {
+        if start >= end || end > self.frames.len() {
+            let msg = format!("Tag {:?} covers frames {}..{}, but the animation has {} frames",
+                              name,
+                              start,
+                              end,
+                              self.frames.len());
+            return Err(GameError::RenderError(msg));
+        }
+        self.tags.insert(name.to_owned(), (start, end));
+        Ok(())
+    }
+
+    /// Restarts the animation, playing only the frames in the given tag.
+    pub fn play_tag(&mut self, name: &str) -> GameResult<()> {
+        match self.tags.get(name) {
+            Some(&range) => self.range = range,
+            None => {
+                let msg = format!("Animation has no tag named {:?}", name);
+                return Err(GameError::RenderError(msg));
+            }
+        }
+        self.reset();
+        Ok(())
+    }
+
+    /// Restarts the animation, playing all of its frames.
+    pub fn play_all(&mut self) {
+        self.range = (0, self.frames.len());
+        self.reset();
+    }
+
+    /// Goes back to the start of the frames being played.
+    pub fn reset(&mut self) {
+        self.position = self.range.0;
+        self.elapsed = Duration::new(0, 0);
+        self.forward = true;
+        self.finished = false;
+    }
+
+    /// Returns true if the animation is in `Once` mode and
+    /// has finished on its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the animation mode.
+    pub fn get_mode(&self) -> AnimationMode {
+        self.mode
+    }
+
+    /// Sets the animation mode.
+    pub fn set_mode(&mut self, mode: AnimationMode) {
+        self.mode = mode;
+        self.forward = true;
+        self.finished = false;
+    }
+
+    /// Returns true if the animation is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or unpauses the animation; while paused, `update()`
+    /// does nothing.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames_over(anim: &mut Animation, steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                     anim.update(Duration::from_millis(100));
+                     anim.current_frame()
+                 })
+            .collect()
+    }
+
+    #[test]
+    fn test_grid_frames() {
+        let frames = grid_frames(64, 40, 32, 16);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0], Rect::new(0.0, 0.0, 0.5, 0.4));
+        assert_eq!(frames[3], Rect::new(0.5, 0.4, 0.5, 0.4));
+    }
+
+    #[test]
+    fn test_animation_modes() {
+        let duration = Duration::from_millis(100);
+        let mut looping = Animation::new(vec![5, 6, 7], duration, AnimationMode::Loop);
+        assert_eq!(looping.current_frame(), 5);
+        assert_eq!(frames_over(&mut looping, 4), vec![6, 7, 5, 6]);
+
+        let mut ping_pong = Animation::new(vec![5, 6, 7], duration, AnimationMode::PingPong);
+        assert_eq!(frames_over(&mut ping_pong, 5), vec![6, 7, 6, 5, 6]);
+
+        let mut once = Animation::new(vec![5, 6, 7], duration, AnimationMode::Once);
+        assert_eq!(frames_over(&mut once, 4), vec![6, 7, 7, 7]);
+        assert!(once.is_finished());
+
+        let mut skipping = Animation::new(vec![5, 6, 7], duration, AnimationMode::Loop);
+        skipping.update(Duration::from_millis(250));
+        assert_eq!(skipping.current_frame(), 7);
+    }
+
+    #[test]
+    fn test_animation_tags() {
+        let mut anim = Animation::new(vec![0, 1, 2, 3, 4],
+                                      Duration::from_millis(100),
+                                      AnimationMode::Loop);
+        assert!(anim.add_tag("bad", 3, 6).is_err());
+        anim.add_tag("walk", 2, 4).unwrap();
+        anim.play_tag("walk").unwrap();
+        assert_eq!(anim.current_frame(), 2);
+        assert_eq!(frames_over(&mut anim, 3), vec![3, 2, 3]);
+        assert!(anim.play_tag("run").is_err());
+    }
+}
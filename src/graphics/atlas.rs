@@ -0,0 +1,442 @@
+//! Texture atlases, which pack many small images into a few big ones.
+//!
+//! Every `Image` is its own texture on the GPU, and switching textures
+//! between draws is slow and stops a `SpriteBatch` from drawing them all
+//! at once.  A `TextureAtlasBuilder` takes a bunch of images and packs them
+//! into one or more pages, each of which becomes a single `Image`, and
+//! remembers where each one went as a rect that can be used as
+//! `DrawParam.src`.
+//!
+//! Packing can also be done ahead of time; `PackedAtlas::save()` writes
+//! out the pages and a TOML manifest, which `TextureAtlas::load()` reads
+//! back in.
+//!
+//! ```rust,ignore
+//! let mut builder = graphics::TextureAtlasBuilder::new(1024, 1024);
+//! builder.set_padding(2);
+//! builder.add_image(ctx, "player", "/player.png")?;
+//! builder.add_image(ctx, "rock", "/rock.png")?;
+//! let atlas = builder.build(ctx)?;
+//!
+//! let player = atlas.get("player").unwrap();
+//! graphics::draw_ex(ctx, atlas.get_page(player.page).unwrap(), graphics::DrawParam {
+//!     src: player.src,
+//!     dest: player_pos,
+//!     ..Default::default()
+//! })?;
+//! ```
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path;
+
+use image;
+use toml;
+
+use super::*;
+
+/// Where an image ended up in a `TextureAtlas`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasRegion {
+    /// The index of the page the image is on.
+    pub page: usize,
+    /// The part of the page the image covers, usable as `DrawParam.src`.
+    pub src: Rect,
+}
+
+/// A set of images packed into a few `Image`s, looked up by name.
+#[derive(Debug, Clone)]
+pub struct TextureAtlas {
+    pages: Vec<Image>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    /// Loads an atlas saved with `PackedAtlas::save()`, given the path
+    /// to its manifest.
+    pub fn load<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<TextureAtlas> {
+        PackedAtlas::load(ctx, path)?.upload(ctx)
+    }
+
+    /// Returns where the image with the given name is in the atlas.
+    pub fn get(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).cloned()
+    }
+
+    /// Returns the given page of the atlas.
+    pub fn get_page(&self, page: usize) -> Option<&Image> {
+        self.pages.get(page)
+    }
+
+    /// Returns all the pages of the atlas.
+    pub fn pages(&self) -> &[Image] {
+        &self.pages
+    }
+
+    /// Draws the image with the given name.  The `src` field of
+    /// the `DrawParam` is replaced by the image's place in the atlas.
+    pub fn draw_region(&self, ctx: &mut Context, name: &str, param: DrawParam) -> GameResult<()> {
+        match self.get(name) {
+            Some(region) => {
+                self.pages[region.page].draw_ex(ctx, DrawParam { src: region.src, ..param })
+            }
+            None => {
+                let msg = format!("No image named {:?} in the TextureAtlas", name);
+                Err(GameError::RenderError(msg))
+            }
+        }
+    }
+}
+
+/// The manifest saved along with the pages of a `PackedAtlas`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct AtlasManifest {
+    /// Page file names, relative to the manifest.
+    pages: Vec<String>,
+    regions: Vec<ManifestRegion>,
+}
+
+/// Where an image is in a page, in pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ManifestRegion {
+    name: String,
+    page: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// A texture atlas that has been packed but not yet uploaded to the GPU.
+#[derive(Debug, Clone)]
+pub struct PackedAtlas {
+    pages: Vec<ImageData>,
+    regions: Vec<ManifestRegion>,
+}
+
+impl PackedAtlas {
+    /// Loads an atlas saved with `save()`, given the path to its manifest.
+    pub fn load<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<PackedAtlas> {
+        let path = path.as_ref();
+        let manifest: AtlasManifest = {
+            let mut s = String::new();
+            let mut reader = ctx.filesystem.open(path)?;
+            reader.read_to_string(&mut s)?;
+            toml::from_str(&s)?
+        };
+        let mut pages = Vec::with_capacity(manifest.pages.len());
+        for page in &manifest.pages {
+            let page_path = path.with_file_name(page);
+            let img = {
+                let mut buf = Vec::new();
+                let mut reader = ctx.filesystem.open(page_path)?;
+                reader.read_to_end(&mut buf)?;
+                image::load_from_memory(&buf)?.to_rgba()
+            };
+            let (width, height) = img.dimensions();
+            pages.push(ImageData::from_rgba8(width, height, img.into_raw())?);
+        }
+        if let Some(region) = manifest.regions.iter().find(|r| r.page >= pages.len()) {
+            let msg = format!("Atlas region {:?} is on page {}, but there are only {} pages",
+                              region.name,
+                              region.page,
+                              pages.len());
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        Ok(PackedAtlas {
+               pages: pages,
+               regions: manifest.regions,
+           })
+    }
+
+    /// Saves the atlas to the user data directory: a TOML manifest at the
+    /// given path, and each page as a PNG file next to it, named after it.
+    /// For instance, saving to `/sprites.toml` also writes `/sprites_0.png`.
+    pub fn save<P: AsRef<path::Path>>(&self, ctx: &mut Context, path: P) -> GameResult<()> {
+        let path = path.as_ref();
+        let stem = path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("atlas"));
+        let mut page_names = Vec::with_capacity(self.pages.len());
+        for (i, page) in self.pages.iter().enumerate() {
+            let name = format!("{}_{}.png", stem, i);
+            page.save_png(ctx, path.with_file_name(&name))?;
+            page_names.push(name);
+        }
+        let manifest = AtlasManifest {
+            pages: page_names,
+            regions: self.regions.clone(),
+        };
+        let s = toml::to_vec(&manifest)?;
+        let mut file = ctx.filesystem.create(path)?;
+        file.write_all(&s)?;
+        Ok(())
+    }
+
+    /// Returns the pixel data of the atlas's pages.
+    pub fn pages(&self) -> &[ImageData] {
+        &self.pages
+    }
+
+    /// Uploads the pages to the GPU, making a `TextureAtlas`.
+    pub fn upload(&self, ctx: &mut Context) -> GameResult<TextureAtlas> {
+        let mut pages = Vec::with_capacity(self.pages.len());
+        for page in &self.pages {
+            // This checks that the page isn't too big for a texture.
+            pages.push(Image::from_image_data(ctx, page)?);
+        }
+        let regions = self.regions
+            .iter()
+            .map(|r| {
+                let page = &self.pages[r.page];
+                let (w, h) = (page.width() as f32, page.height() as f32);
+                let src = Rect::new(r.x as f32 / w,
+                                    r.y as f32 / h,
+                                    r.width as f32 / w,
+                                    r.height as f32 / h);
+                (r.name.clone(),
+                 AtlasRegion {
+                     page: r.page,
+                     src: src,
+                 })
+            })
+            .collect();
+        Ok(TextureAtlas {
+               pages: pages,
+               regions: regions,
+           })
+    }
+}
+
+/// Collects images to pack into a `TextureAtlas`.
+#[derive(Debug, Clone)]
+pub struct TextureAtlasBuilder {
+    page_width: u32,
+    page_height: u32,
+    padding: u32,
+    extrude: u32,
+    images: Vec<(String, ImageData)>,
+}
+
+impl TextureAtlasBuilder {
+    /// Creates a new `TextureAtlasBuilder` making pages of the given size.
+    pub fn new(page_width: u32, page_height: u32) -> Self {
+        TextureAtlasBuilder {
+            page_width: page_width,
+            page_height: page_height,
+            padding: 0,
+            extrude: 0,
+            images: Vec::new(),
+        }
+    }
+
+    /// Sets the number of transparent pixels left between images.
+    pub fn set_padding(&mut self, padding: u32) {
+        self.padding = padding;
+    }
+
+    /// Sets the number of pixels each image's edges are repeated outwards
+    /// by.  This keeps neighboring images from bleeding in around the
+    /// edges when drawing with linear filtering or at fractional positions.
+    pub fn set_extrude(&mut self, extrude: u32) {
+        self.extrude = extrude;
+    }
+
+    /// Loads the image file at the given path and adds it under the given name.
+    pub fn add_image<P: AsRef<path::Path>>(&mut self,
+                                           ctx: &mut Context,
+                                           name: &str,
+                                           path: P)
+                                           -> GameResult<()> {
+        let img = {
+            let mut buf = Vec::new();
+            let mut reader = ctx.filesystem.open(path)?;
+            reader.read_to_end(&mut buf)?;
+            image::load_from_memory(&buf)?.to_rgba()
+        };
+        let (width, height) = img.dimensions();
+        self.add_rgba8(name, width, height, img.into_raw())
+    }
+
+    /// Adds an image from the given buffer of `u8` RGBA values.
+    pub fn add_rgba8(&mut self,
+                     name: &str,
+                     width: u32,
+                     height: u32,
+                     rgba: Vec<u8>)
+                     -> GameResult<()> {
+        let data = ImageData::from_rgba8(width, height, rgba)?;
+        self.add_image_data(name, data);
+        Ok(())
+    }
+
+    /// Adds an image from an `ImageData`.
+    pub fn add_image_data(&mut self, name: &str, data: ImageData) {
+        self.images.push((name.to_owned(), data));
+    }
+
+    /// Packs the images into pages, without uploading them to the GPU.
+    pub fn pack(&self) -> GameResult<PackedAtlas> {
+        let border = self.extrude * 2;
+        let sizes: Vec<(u32, u32)> = self.images
+            .iter()
+            .map(|&(_, ref data)| (data.width() + border, data.height() + border))
+            .collect();
+        let places = pack_shelves(&sizes, self.page_width, self.page_height, self.padding)?;
+
+        let page_count = places.iter().map(|&(page, _, _)| page + 1).max().unwrap_or(0);
+        let page_size = self.page_width as usize * self.page_height as usize * 4;
+        let mut pages = vec![vec![0u8; page_size]; page_count];
+        let mut regions = Vec::with_capacity(self.images.len());
+        for (&(ref name, ref data), &(page, x, y)) in self.images.iter().zip(&places) {
+            blit_extruded(&mut pages[page], self.page_width, data, x, y, self.extrude);
+            regions.push(ManifestRegion {
+                             name: name.clone(),
+                             page: page,
+                             x: x + self.extrude,
+                             y: y + self.extrude,
+                             width: data.width(),
+                             height: data.height(),
+                         });
+        }
+        let mut page_data = Vec::with_capacity(page_count);
+        for page in pages {
+            page_data.push(ImageData::from_rgba8(self.page_width, self.page_height, page)?);
+        }
+        Ok(PackedAtlas {
+               pages: page_data,
+               regions: regions,
+           })
+    }
+
+    /// Packs the images and uploads the pages to the GPU.
+    pub fn build(&self, ctx: &mut Context) -> GameResult<TextureAtlas> {
+        self.pack()?.upload(ctx)
+    }
+}
+
+/// Places rectangles of the given sizes on pages of the given size,
+/// in rows ("shelves") from tallest to shortest, returning the page
+/// and position of each one.
+fn pack_shelves(sizes: &[(u32, u32)],
+                page_width: u32,
+                page_height: u32,
+                padding: u32)
+                -> GameResult<Vec<(usize, u32, u32)>> {
+    // The current shelf on each page: its top, height, and how far along it we are.
+    struct Shelf {
+        y: u32,
+        height: u32,
+        x: u32,
+    }
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| sizes[b].1.cmp(&sizes[a].1).then(sizes[b].0.cmp(&sizes[a].0)));
+
+    let mut shelves: Vec<Shelf> = Vec::new();
+    let mut places = vec![(0, 0, 0); sizes.len()];
+    for i in order {
+        let (width, height) = sizes[i];
+        if width > page_width || height > page_height {
+            let msg = format!("A {}x{} image can't fit on a {}x{} atlas page",
+                              width,
+                              height,
+                              page_width,
+                              page_height);
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        let mut placed = false;
+        for (page, shelf) in shelves.iter_mut().enumerate() {
+            if shelf.x + width <= page_width && shelf.y + height <= page_height {
+                places[i] = (page, shelf.x, shelf.y);
+                shelf.x += width + padding;
+                shelf.height = shelf.height.max(height);
+                placed = true;
+                break;
+            }
+            let next_y = shelf.y + shelf.height + padding;
+            if next_y + height <= page_height {
+                places[i] = (page, 0, next_y);
+                *shelf = Shelf {
+                    y: next_y,
+                    height: height,
+                    x: width + padding,
+                };
+                placed = true;
+                break;
+            }
+        }
+        if !placed {
+            places[i] = (shelves.len(), 0, 0);
+            shelves.push(Shelf {
+                             y: 0,
+                             height: height,
+                             x: width + padding,
+                         });
+        }
+    }
+    Ok(places)
+}
+
+/// Copies an image into a page at the given position, repeating
+/// its edge pixels outwards by `extrude` pixels on each side.
+/// An empty image has no edge pixels, so it leaves the page alone.
+fn blit_extruded(page: &mut [u8], page_width: u32, data: &ImageData, x: u32, y: u32, extrude: u32) {
+    let (width, height) = (data.width() as i64, data.height() as i64);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let extrude = extrude as i64;
+    let src = data.as_rgba8();
+    for dy in -extrude..height + extrude {
+        let sy = dy.max(0).min(height - 1);
+        for dx in -extrude..width + extrude {
+            let sx = dx.max(0).min(width - 1);
+            let src_index = ((sy * width + sx) * 4) as usize;
+            let px = (x as i64 + extrude + dx) as usize;
+            let py = (y as i64 + extrude + dy) as usize;
+            let dest_index = (py * page_width as usize + px) * 4;
+            page[dest_index..dest_index + 4].copy_from_slice(&src[src_index..src_index + 4]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_shelves() {
+        let sizes = [(12, 10), (30, 20), (20, 20), (64, 64)];
+        let places = pack_shelves(&sizes, 64, 64, 2).unwrap();
+        assert_eq!(places[3], (0, 0, 0));
+        assert_eq!(places[1], (1, 0, 0));
+        assert_eq!(places[2], (1, 32, 0));
+        assert_eq!(places[0], (1, 0, 22));
+
+        assert!(pack_shelves(&[(65, 1)], 64, 64, 0).is_err());
+    }
+
+    #[test]
+    fn test_blit_extruded() {
+        let data = ImageData::from_rgba8(1, 1, vec![1, 2, 3, 4]).unwrap();
+        let mut page = vec![0; 4 * 4 * 4];
+        blit_extruded(&mut page, 4, &data, 1, 1, 1);
+        for y in 0..4 {
+            for x in 0..4 {
+                let i = (y * 4 + x) * 4;
+                let expected = if x >= 1 && x <= 3 && y >= 1 && y <= 3 {
+                    [1, 2, 3, 4]
+                } else {
+                    [0, 0, 0, 0]
+                };
+                assert_eq!(&page[i..i + 4], &expected);
+            }
+        }
+
+        let empty = ImageData::from_rgba8(0, 3, vec![]).unwrap();
+        let mut page = vec![0; 4 * 4 * 4];
+        blit_extruded(&mut page, 4, &empty, 1, 1, 1);
+        assert!(page.iter().all(|&b| b == 0));
+    }
+}
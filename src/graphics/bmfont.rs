@@ -0,0 +1,270 @@
+//! Parsing of the descriptor files of AngelCode BMFont bitmap fonts,
+//! in either their text or binary format.  `Font::new_bmfont()` loads
+//! the fonts themselves.
+//!
+//! The formats are described at
+//! http://www.angelcode.com/products/bmfont/doc/file_format.html
+
+use std::char;
+use std::collections::BTreeMap;
+use std::mem;
+use std::str;
+use std::str::FromStr;
+
+use super::BitmapGlyph;
+use GameError;
+use GameResult;
+
+/// What a BMFont descriptor says about its font.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Descriptor {
+    /// How far apart lines of text are, in pixels.
+    pub line_height: usize,
+    /// How far below the top of a line its baseline is, in pixels.
+    pub base: usize,
+    /// The file names of the page images, by page number.
+    pub pages: Vec<String>,
+    /// Each glyph, with the page it's on.  Its position is on that page.
+    pub glyphs: Vec<(char, usize, BitmapGlyph)>,
+    /// How much to move the second of each pair of characters right
+    /// when it follows the first, in pixels; usually negative.
+    pub kerning: Vec<(char, char, i32)>,
+}
+
+fn load_error(msg: String) -> GameError {
+    GameError::ResourceLoadError(format!("Invalid BMFont file: {}", msg))
+}
+
+fn to_char(id: u32) -> GameResult<char> {
+    char::from_u32(id).ok_or_else(|| load_error(format!("{} is not a valid character", id)))
+}
+
+/// Parses a BMFont descriptor, working out from its first bytes
+/// which format it's in.
+pub fn parse(bytes: &[u8]) -> GameResult<Descriptor> {
+    if bytes.starts_with(b"BMF") {
+        parse_binary(bytes)
+    } else {
+        let text = str::from_utf8(bytes)
+            .map_err(|_| load_error(String::from("text descriptor is not UTF-8")))?;
+        parse_text(text)
+    }
+}
+
+/// Splits a line of a text descriptor at whitespace, except inside
+/// quotes, which are removed.
+fn tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(mem::replace(&mut current, String::new()));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn field<T: FromStr>(fields: &BTreeMap<&str, &str>, key: &str, line: usize) -> GameResult<T> {
+    fields
+        .get(key)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| load_error(format!("line {} has no valid `{}`", line, key)))
+}
+
+fn parse_text(text: &str) -> GameResult<Descriptor> {
+    let mut descriptor = Descriptor::default();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let tokens = tokens(line);
+        let (tag, rest) = match tokens.split_first() {
+            Some((tag, rest)) => (tag.as_str(), rest),
+            None => continue,
+        };
+        let fields: BTreeMap<&str, &str> = rest.iter()
+            .filter_map(|token| {
+                            let mut parts = token.splitn(2, '=');
+                            match (parts.next(), parts.next()) {
+                                (Some(key), Some(value)) => Some((key, value)),
+                                _ => None,
+                            }
+                        })
+            .collect();
+        match tag {
+            "common" => {
+                descriptor.line_height = field(&fields, "lineHeight", line_number)?;
+                descriptor.base = field(&fields, "base", line_number)?;
+            }
+            "page" => {
+                let id: usize = field(&fields, "id", line_number)?;
+                let file: String = field(&fields, "file", line_number)?;
+                if descriptor.pages.len() <= id {
+                    descriptor.pages.resize(id + 1, String::new());
+                }
+                descriptor.pages[id] = file;
+            }
+            "char" => {
+                let c = to_char(field(&fields, "id", line_number)?)?;
+                let glyph = BitmapGlyph {
+                    x: field(&fields, "x", line_number)?,
+                    y: field(&fields, "y", line_number)?,
+                    width: field(&fields, "width", line_number)?,
+                    height: field(&fields, "height", line_number)?,
+                    x_offset: field(&fields, "xoffset", line_number)?,
+                    y_offset: field(&fields, "yoffset", line_number)?,
+                    advance: field(&fields, "xadvance", line_number)?,
+                };
+                let page = field(&fields, "page", line_number)?;
+                descriptor.glyphs.push((c, page, glyph));
+            }
+            "kerning" => {
+                let first = to_char(field(&fields, "first", line_number)?)?;
+                let second = to_char(field(&fields, "second", line_number)?)?;
+                let amount = field(&fields, "amount", line_number)?;
+                descriptor.kerning.push((first, second, amount));
+            }
+            // "info", "chars" and "kernings" don't tell us anything we need.
+            _ => (),
+        }
+    }
+    Ok(descriptor)
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    bytes[at] as u16 | (bytes[at + 1] as u16) << 8
+}
+
+fn read_i16(bytes: &[u8], at: usize) -> i16 {
+    read_u16(bytes, at) as i16
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    read_u16(bytes, at) as u32 | (read_u16(bytes, at + 2) as u32) << 16
+}
+
+fn parse_binary(bytes: &[u8]) -> GameResult<Descriptor> {
+    if bytes.len() < 4 || bytes[3] != 3 {
+        return Err(load_error(String::from("only version 3 of the binary format is supported")));
+    }
+    let mut descriptor = Descriptor::default();
+    let mut at = 4;
+    while at < bytes.len() {
+        if at + 5 > bytes.len() {
+            return Err(load_error(String::from("truncated block header")));
+        }
+        let block_type = bytes[at];
+        let size = read_u32(bytes, at + 1) as usize;
+        at += 5;
+        if at + size > bytes.len() {
+            return Err(load_error(format!("block {} runs past the end of the file", block_type)));
+        }
+        let block = &bytes[at..at + size];
+        at += size;
+        match block_type {
+            2 => {
+                if block.len() < 4 {
+                    return Err(load_error(String::from("common block is too short")));
+                }
+                descriptor.line_height = read_u16(block, 0) as usize;
+                descriptor.base = read_u16(block, 2) as usize;
+            }
+            3 => {
+                descriptor.pages = block
+                    .split(|&b| b == 0)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .collect();
+            }
+            4 => {
+                for entry in block.chunks(20).filter(|entry| entry.len() == 20) {
+                    let c = to_char(read_u32(entry, 0))?;
+                    let glyph = BitmapGlyph {
+                        x: read_u16(entry, 4) as usize,
+                        y: read_u16(entry, 6) as usize,
+                        width: read_u16(entry, 8) as usize,
+                        height: read_u16(entry, 10) as usize,
+                        x_offset: read_i16(entry, 12) as i32,
+                        y_offset: read_i16(entry, 14) as i32,
+                        advance: read_i16(entry, 16) as i32,
+                    };
+                    descriptor.glyphs.push((c, entry[18] as usize, glyph));
+                }
+            }
+            5 => {
+                for entry in block.chunks(10).filter(|entry| entry.len() == 10) {
+                    let first = to_char(read_u32(entry, 0))?;
+                    let second = to_char(read_u32(entry, 4))?;
+                    descriptor.kerning.push((first, second, read_i16(entry, 8) as i32));
+                }
+            }
+            // Block 1 is the info block, which we don't need.
+            _ => (),
+        }
+    }
+    Ok(descriptor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected() -> Descriptor {
+        Descriptor {
+            line_height: 32,
+            base: 26,
+            pages: vec![String::from("my font_0.png")],
+            glyphs: vec![('A',
+                          0,
+                          BitmapGlyph {
+                              x: 10,
+                              y: 20,
+                              width: 14,
+                              height: 18,
+                              x_offset: -1,
+                              y_offset: 8,
+                              advance: 13,
+                          })],
+            kerning: vec![('A', 'V', -2)],
+        }
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let text = "info face=\"Some Font\" size=32\n\
+                    common lineHeight=32 base=26 scaleW=256 scaleH=256 pages=1\n\
+                    page id=0 file=\"my font_0.png\"\n\
+                    chars count=1\n\
+                    char id=65   x=10  y=20  width=14  height=18  xoffset=-1  yoffset=8  \
+                    xadvance=13  page=0  chnl=15\n\
+                    kernings count=1\n\
+                    kerning first=65 second=86 amount=-2\n";
+        assert_eq!(parse(text.as_bytes()).unwrap(), expected());
+        assert!(parse(b"char id=65 x=oops").is_err());
+    }
+
+    #[test]
+    fn test_parse_binary() {
+        let mut bytes = b"BMF\x03".to_vec();
+        // An info block, which gets skipped.
+        bytes.extend_from_slice(&[1, 2, 0, 0, 0, 32, 0]);
+        bytes.extend_from_slice(&[2, 15, 0, 0, 0, 32, 0, 26, 0, 0, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[3, 14, 0, 0, 0]);
+        bytes.extend_from_slice(b"my font_0.png\0");
+        bytes.extend_from_slice(&[4, 20, 0, 0, 0, 65, 0, 0, 0, 10, 0, 20, 0, 14, 0, 18, 0,
+                                  0xff, 0xff, 8, 0, 13, 0, 0, 15]);
+        bytes.extend_from_slice(&[5, 10, 0, 0, 0, 65, 0, 0, 0, 86, 0, 0, 0, 0xfe, 0xff]);
+        assert_eq!(parse(&bytes).unwrap(), expected());
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(parse(&bytes).is_err());
+    }
+}
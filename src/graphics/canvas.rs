@@ -0,0 +1,148 @@
+//! A `Canvas` is an offscreen render target that can be drawn to
+//! like the screen, then drawn onto the screen itself like an `Image`.
+//!
+//! Useful for things like minimaps, caching parts of a scene that rarely
+//! change, or rendering at a low resolution and scaling up.
+
+use gfx;
+use gfx::Factory;
+use gfx::memory::Typed;
+
+use super::*;
+
+/// A generic render target that is also a `Drawable`.
+pub struct CanvasGeneric<R>
+    where R: gfx::Resources
+{
+    target: gfx::handle::RenderTargetView<R, ColorFormat>,
+    depth: gfx::handle::DepthStencilView<R, DepthFormat>,
+    image: ImageGeneric<R>,
+}
+
+/// A render target that is also a `Drawable`, using the OpenGL backend.
+pub type Canvas = CanvasGeneric<gfx_device_gl::Resources>;
+
+impl Canvas {
+    /// Creates a new `Canvas` with the given size in pixels.
+    ///
+    /// While the canvas is the render target, the coordinate system
+    /// set with `set_screen_coordinates()` is stretched to cover the
+    /// whole canvas, just like it covers the whole window normally.
+    pub fn new(ctx: &mut Context, width: u32, height: u32) -> GameResult<Canvas> {
+        if width == 0 || height == 0 || width > u16::MAX as u32 || height > u16::MAX as u32 {
+            let msg = format!("Tried to create a canvas of size {}x{}, each dimension must be \
+                               >0 and <= {}",
+                              width,
+                              height,
+                              u16::MAX);
+            return Err(GameError::RenderError(msg));
+        }
+        use gfx::format::{ChannelTyped, Formatted};
+
+        let gfx = &mut ctx.gfx_context;
+        let kind = texture::Kind::D2(width as u16, height as u16, texture::AaMode::Single);
+        let levels = 1;
+        // Like Factory::create_render_target(), but also lets the texture be
+        // copied to and from, like any other `Image`.
+        let bind = gfx::SHADER_RESOURCE | gfx::RENDER_TARGET | gfx::TRANSFER_SRC |
+                   gfx::TRANSFER_DST;
+        let channel = <<ColorFormat as Formatted>::Channel as ChannelTyped>::get_channel_type();
+        let tex = gfx.factory
+            .create_texture(kind, levels, bind, gfx::memory::Usage::Data, Some(channel))?;
+        let view = gfx.factory
+            .view_texture_as_shader_resource::<ColorFormat>(&tex,
+                                                            (0, levels - 1),
+                                                            gfx::format::Swizzle::new())?;
+        let target = gfx.factory.view_texture_as_render_target(&tex, 0, None)?;
+        // Its own stencil buffer, since the screen's may be the wrong size.
+        let depth = gfx.factory
+            .create_depth_stencil_view_only(width as u16, height as u16)?;
+        Ok(Canvas {
+               target: target,
+               depth: depth,
+               image: Image {
+                   texture: view,
+                   raw_texture: tex.raw().clone(),
+                   sampler_info: gfx.default_sampler_info,
+                   width: width,
+                   height: height,
+               },
+           })
+    }
+
+    /// Creates a new `Canvas` the same size as the window.
+    pub fn with_window_size(ctx: &mut Context) -> GameResult<Canvas> {
+        let (width, height) = ctx.gfx_context.window.drawable_size();
+        Canvas::new(ctx, width, height)
+    }
+
+    /// Returns the `Image` holding the canvas's contents.
+    ///
+    /// Note that it is upside-down compared to what is drawn to it,
+    /// since OpenGL considers the bottom-left to be the origin.
+    /// Drawing the `Canvas` itself rather than this `Image` accounts
+    /// for that.
+    pub fn get_image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Return the width of the canvas.
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    /// Return the height of the canvas.
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    /// Get the filter mode for the canvas.
+    pub fn get_filter(&self) -> FilterMode {
+        self.image.get_filter()
+    }
+
+    /// Set the filter mode for the canvas.
+    pub fn set_filter(&mut self, mode: FilterMode) {
+        self.image.set_filter(mode)
+    }
+
+    /// Returns the dimensions of the canvas.
+    pub fn get_dimensions(&self) -> Rect {
+        self.image.get_dimensions()
+    }
+}
+
+impl fmt::Debug for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "<Canvas: {}x{}, {:p}>",
+               self.width(),
+               self.height(),
+               self)
+    }
+}
+
+impl Drawable for Canvas {
+    fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
+        let gfx = &mut ctx.gfx_context;
+        let mut new_param = self.image.scale_draw_param(param, &gfx.screen_rect);
+        // Flip the texture coordinates vertically, since the texture
+        // was rendered with its origin at the bottom-left.
+        new_param.src.y += new_param.src.h;
+        new_param.src.h = -new_param.src.h;
+        self.image.draw_scaled(gfx, new_param)
+    }
+}
+
+/// Sets the render target that all subsequent drawing goes to.
+/// `Some(canvas)` will draw to the given `Canvas`, `None` goes back
+/// to drawing to the screen.
+pub fn set_canvas(ctx: &mut Context, target: Option<&Canvas>) {
+    let gfx = &mut ctx.gfx_context;
+    let (out, depth) = match target {
+        Some(canvas) => (canvas.target.clone(), canvas.depth.clone()),
+        None => (gfx.screen_render_target.clone(), gfx.depth_view.clone()),
+    };
+    gfx.data.out = out;
+    gfx.data.stencil.0 = depth;
+}
@@ -0,0 +1,539 @@
+//! CPU-side image data, as opposed to an `Image`, which lives on the GPU,
+//! and ways to move pixels between the two.
+
+use std::fmt;
+use std::io::Write;
+use std::path;
+use std::u16;
+
+use image;
+
+use super::*;
+
+/// A buffer of RGBA8 pixel data in main memory,
+/// stored row by row starting from the top-left.
+#[derive(Clone, PartialEq)]
+pub struct ImageData {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl ImageData {
+    /// Creates a new `ImageData` of the given size, filled with
+    /// transparent black.
+    pub fn new(width: u32, height: u32) -> ImageData {
+        ImageData {
+            width: width,
+            height: height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Creates a new `ImageData` from the given buffer of `u8` RGBA values.
+    pub fn from_rgba8(width: u32, height: u32, rgba: Vec<u8>) -> GameResult<ImageData> {
+        let expected = width as usize * height as usize * 4;
+        if rgba.len() != expected {
+            let msg = format!("Image data for a {}x{} image should be {} bytes, got {}",
+                              width,
+                              height,
+                              expected,
+                              rgba.len());
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        Ok(ImageData {
+               width: width,
+               height: height,
+               data: rgba,
+           })
+    }
+
+    /// Return the width of the image.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Return the height of the image.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the raw RGBA bytes of the image.
+    pub fn as_rgba8(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consumes the `ImageData` and returns its raw RGBA bytes.
+    pub fn into_rgba8(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Returns the index into the data of the given pixel, if it's in the image.
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some((y as usize * self.width as usize + x as usize) * 4)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the color of the pixel at the given position,
+    /// or `None` if it's outside the image.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<Color> {
+        self.index(x, y).map(|i| {
+                                 let p = &self.data[i..i + 4];
+                                 Color::from((p[0], p[1], p[2], p[3]))
+                             })
+    }
+
+    /// Sets the color of the pixel at the given position.
+    /// Returns an error if it's outside the image.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) -> GameResult<()> {
+        match self.index(x, y) {
+            Some(i) => {
+                let rgba: [u8; 4] = color.into();
+                self.data[i..i + 4].copy_from_slice(&rgba);
+                Ok(())
+            }
+            None => {
+                let msg = format!("Pixel ({}, {}) is outside of a {}x{} image",
+                                  x,
+                                  y,
+                                  self.width,
+                                  self.height);
+                Err(GameError::RenderError(msg))
+            }
+        }
+    }
+
+    /// Sets every pixel in the image to the given color.
+    pub fn fill(&mut self, color: Color) {
+        let rgba: [u8; 4] = color.into();
+        for pixel in self.data.chunks_mut(4) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    /// Copies another image onto this one with its top-left corner at the
+    /// given position, replacing the pixels underneath.  Any parts that
+    /// fall outside of this image are left out.
+    pub fn blit(&mut self, src: &ImageData, x: i32, y: i32) {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let (x, y) = (x as i64, y as i64);
+        let left = x.max(0);
+        let right = (x + src.width as i64).min(width);
+        if left >= right {
+            return;
+        }
+        for dest_y in y.max(0)..(y + src.height as i64).min(height) {
+            let src_y = dest_y - y;
+            let src_start = ((src_y * src.width as i64 + (left - x)) * 4) as usize;
+            let dest_start = ((dest_y * width + left) * 4) as usize;
+            let len = ((right - left) * 4) as usize;
+            self.data[dest_start..dest_start + len]
+                .copy_from_slice(&src.data[src_start..src_start + len]);
+        }
+    }
+
+    /// Flips the image left to right.
+    pub fn flip_horizontal(&mut self) {
+        let row_len = self.width as usize * 4;
+        if row_len == 0 {
+            return;
+        }
+        for row in self.data.chunks_mut(row_len) {
+            let width = row.len() / 4;
+            for x in 0..width / 2 {
+                for c in 0..4 {
+                    row.swap(x * 4 + c, (width - 1 - x) * 4 + c);
+                }
+            }
+        }
+    }
+
+    /// Flips the image upside down.
+    pub fn flip_vertical(&mut self) {
+        let row_len = self.width as usize * 4;
+        let height = self.height as usize;
+        for y in 0..height / 2 {
+            let top = y * row_len;
+            let bottom = (height - 1 - y) * row_len;
+            for i in 0..row_len {
+                self.data.swap(top + i, bottom + i);
+            }
+        }
+    }
+
+    /// Returns a new `ImageData` containing the given rectangle of
+    /// this one, or an error if it doesn't fit within this one.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> GameResult<ImageData> {
+        if x as u64 + width as u64 > self.width as u64 ||
+           y as u64 + height as u64 > self.height as u64 {
+            let msg = format!("Can't crop {}x{} at ({}, {}) from a {}x{} image",
+                              width,
+                              height,
+                              x,
+                              y,
+                              self.width,
+                              self.height);
+            return Err(GameError::RenderError(msg));
+        }
+        // Copying rows directly, rather than blitting at a negative
+        // offset, works for offsets too big for an i32.
+        let row_len = width as usize * 4;
+        let mut data = Vec::with_capacity(row_len * height as usize);
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * 4;
+            data.extend_from_slice(&self.data[start..start + row_len]);
+        }
+        ImageData::from_rgba8(width, height, data)
+    }
+
+    /// Uploads the image to the GPU as a new `Image`.
+    pub fn to_image(&self, ctx: &mut Context) -> GameResult<Image> {
+        Image::from_image_data(ctx, self)
+    }
+
+    /// Encodes the image in the given format and writes it to the
+    /// given path in the user data directory.
+    pub fn encode<P: AsRef<path::Path>>(&self,
+                                        ctx: &mut Context,
+                                        format: ImageFormat,
+                                        path: P)
+                                        -> GameResult<()> {
+        let mut file = ctx.filesystem.create(path)?;
+        match format {
+            ImageFormat::Png => {
+                image::png::PNGEncoder::new(&mut file)
+                    .encode(&self.data, self.width, self.height, image::ColorType::RGBA(8))?
+            }
+            ImageFormat::Bmp => {
+                let bmp = encode_bmp(self.width, self.height, &self.data)?;
+                file.write_all(&bmp)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the image as a PNG file and writes it to the given
+    /// path in the user data directory.
+    pub fn save_png<P: AsRef<path::Path>>(&self, ctx: &mut Context, path: P) -> GameResult<()> {
+        self.encode(ctx, ImageFormat::Png, path)
+    }
+}
+
+/// File formats images can be saved in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// PNG, which is compressed without losing anything.
+    Png,
+    /// Windows BMP, which isn't compressed at all.
+    Bmp,
+}
+
+/// Encodes RGBA data as a 32-bit BMP file with an alpha channel.
+///
+/// The `image` crate can read BMPs but not write them, and the format
+/// is simple enough to just do it ourselves.  Fails if the image is
+/// too big for the sizes in a BMP's headers.
+/// Checks that a region of an image of the given size lies inside it,
+/// and that `rgba_len` bytes are exactly enough pixels to fill it.
+#[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+fn check_region(image_width: u32,
+                image_height: u32,
+                x: u16,
+                y: u16,
+                width: u16,
+                height: u16,
+                rgba_len: usize)
+                -> GameResult<()> {
+    if x as u32 + width as u32 > image_width || y as u32 + height as u32 > image_height {
+        let msg = format!("Region of {}x{} at ({}, {}) is outside of a {}x{} image",
+                          width,
+                          height,
+                          x,
+                          y,
+                          image_width,
+                          image_height);
+        return Err(GameError::RenderError(msg));
+    }
+    let expected = width as usize * height as usize * 4;
+    if rgba_len != expected {
+        let msg = format!("Image data for a {}x{} region should be {} bytes, got {}",
+                          width,
+                          height,
+                          expected,
+                          rgba_len);
+        return Err(GameError::RenderError(msg));
+    }
+    Ok(())
+}
+
+fn encode_bmp(width: u32, height: u32, rgba: &[u8]) -> GameResult<Vec<u8>> {
+    fn push_u16(buf: &mut Vec<u8>, v: u16) {
+        buf.extend_from_slice(&[v as u8, (v >> 8) as u8]);
+    }
+    fn push_u32(buf: &mut Vec<u8>, v: u32) {
+        buf.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+    }
+    const FILE_HEADER_SIZE: u32 = 14;
+    // A BITMAPV4HEADER, the simplest one that can describe an alpha channel.
+    const INFO_HEADER_SIZE: u32 = 108;
+    let offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+    let image_size = width.checked_mul(height).and_then(|n| n.checked_mul(4));
+    let file_size = match image_size.and_then(|n| n.checked_add(offset)) {
+        Some(file_size) => file_size,
+        None => {
+            let msg = format!("A {}x{} image is too big to save as a BMP", width, height);
+            return Err(GameError::RenderError(msg));
+        }
+    };
+    let image_size = file_size - offset;
+    let mut buf = Vec::with_capacity(file_size as usize);
+
+    buf.extend_from_slice(b"BM");
+    push_u32(&mut buf, file_size);
+    push_u32(&mut buf, 0); // Reserved
+    push_u32(&mut buf, offset);
+
+    push_u32(&mut buf, INFO_HEADER_SIZE);
+    push_u32(&mut buf, width);
+    // Positive height means the rows are stored bottom to top.
+    push_u32(&mut buf, height);
+    push_u16(&mut buf, 1); // Color planes
+    push_u16(&mut buf, 32); // Bits per pixel
+    push_u32(&mut buf, 3); // BI_BITFIELDS, i.e. uncompressed with the masks below
+    push_u32(&mut buf, image_size);
+    push_u32(&mut buf, 2835); // Horizontal pixels per meter, i.e. 72 DPI
+    push_u32(&mut buf, 2835); // Vertical pixels per meter
+    push_u32(&mut buf, 0); // Palette colors
+    push_u32(&mut buf, 0); // Important colors
+    push_u32(&mut buf, 0x00FF0000); // Red mask
+    push_u32(&mut buf, 0x0000FF00); // Green mask
+    push_u32(&mut buf, 0x000000FF); // Blue mask
+    push_u32(&mut buf, 0xFF000000); // Alpha mask
+    push_u32(&mut buf, 0x73524742); // Color space: "sRGB"
+    // Color space endpoints and gamma, unused for sRGB.
+    buf.extend_from_slice(&[0; 48]);
+
+    let row_len = width as usize * 4;
+    if row_len > 0 {
+        for row in rgba.chunks(row_len).rev() {
+            for pixel in row.chunks(4) {
+                buf.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        }
+    }
+    Ok(buf)
+}
+
+impl fmt::Debug for ImageData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<ImageData: {}x{}, {:p}>", self.width, self.height, self)
+    }
+}
+
+impl Image {
+    /// Reads the image back from the GPU, encodes it in the given format
+    /// and writes it to the given path in the user data directory.
+    pub fn encode<P: AsRef<path::Path>>(&self,
+                                        ctx: &mut Context,
+                                        format: ImageFormat,
+                                        path: P)
+                                        -> GameResult<()> {
+        let data = self.to_image_data(ctx)?;
+        data.encode(ctx, format, path)
+    }
+
+    /// Creates a new `Image` from the pixels of an `ImageData`.
+    pub fn from_image_data(ctx: &mut Context, data: &ImageData) -> GameResult<Image> {
+        if data.width > u16::MAX as u32 || data.height > u16::MAX as u32 {
+            let msg = format!("Tried to create a texture of size {}x{}, each dimension must be \
+                               <= {}",
+                              data.width,
+                              data.height,
+                              u16::MAX);
+            return Err(GameError::ResourceLoadError(msg));
+        }
+        Image::from_rgba8(ctx, data.width as u16, data.height as u16, &data.data)
+    }
+
+    /// Replaces the pixels in the `width` by `height` rectangle at `(x, y)`
+    /// from the top-left of the image with the given buffer of `u8` RGBA
+    /// values.
+    ///
+    /// Clones of an `Image` share the same texture, so they all change.
+    pub fn update_region(&self,
+                         ctx: &mut Context,
+                         x: u16,
+                         y: u16,
+                         width: u16,
+                         height: u16,
+                         rgba: &[u8])
+                         -> GameResult<()> {
+        use gfx::format::Formatted;
+
+        check_region(self.width, self.height, x, y, width, height, rgba.len())?;
+        let info = gfx::texture::RawImageInfo {
+            xoffset: x,
+            yoffset: y,
+            zoffset: 0,
+            width: width,
+            height: height,
+            depth: 0,
+            format: ColorFormat::get_format(),
+            mipmap: 0,
+        };
+        let gfx = &mut ctx.gfx_context;
+        gfx.encoder
+            .update_texture_raw(&self.raw_texture, None, info, rgba)?;
+        if self.raw_texture.get_info().levels > 1 {
+            gfx.encoder.generate_mipmap(&self.texture);
+        }
+        Ok(())
+    }
+
+    /// Reads the image back from the GPU as a buffer of `u8` RGBA values.
+    ///
+    /// Like `screenshot()`, this waits for the GPU to finish drawing.
+    pub fn to_rgba8(&self, ctx: &mut Context) -> GameResult<Vec<u8>> {
+        read_texture(&mut ctx.gfx_context,
+                     &self.raw_texture,
+                     self.width as u16,
+                     self.height as u16)
+    }
+
+    /// Reads the image back from the GPU as an `ImageData`.
+    pub fn to_image_data(&self, ctx: &mut Context) -> GameResult<ImageData> {
+        let rgba = self.to_rgba8(ctx)?;
+        ImageData::from_rgba8(self.width, self.height, rgba)
+    }
+}
+
+/// Copies the contents of a texture back from the GPU, in the order
+/// its rows are stored.  This flushes the encoder, so everything drawn
+/// so far is finished first.
+fn read_texture(gfx: &mut GraphicsContext,
+                texture: &gfx::handle::RawTexture<gfx_device_gl::Resources>,
+                width: u16,
+                height: u16)
+                -> GameResult<Vec<u8>> {
+    use gfx::memory::Typed;
+    use gfx::format::Formatted;
+
+    let (w, h) = (width as usize, height as usize);
+    let download = gfx.factory.create_download_buffer::<[u8; 4]>(w * h)?;
+    let info = gfx::texture::RawImageInfo {
+        xoffset: 0,
+        yoffset: 0,
+        zoffset: 0,
+        width: width,
+        height: height,
+        depth: 0,
+        format: ColorFormat::get_format(),
+        mipmap: 0,
+    };
+    gfx.encoder
+        .copy_texture_to_buffer_raw(texture, None, info, download.raw(), 0)?;
+    gfx.encoder.flush(&mut *gfx.device);
+
+    let reader = gfx.factory.read_mapping(&download)?;
+    let mut data = Vec::with_capacity(w * h * 4);
+    for pixel in reader.iter() {
+        data.extend(pixel);
+    }
+    Ok(data)
+}
+
+/// Reads back the contents of the current render target, either
+/// the screen or the `Canvas` set with `set_canvas()`.
+///
+/// This flushes all drawing done so far and waits for the GPU to
+/// finish it, so it is not something to do every frame.
+pub fn screenshot(ctx: &mut Context) -> GameResult<ImageData> {
+    use gfx::memory::Typed;
+
+    let gfx = &mut ctx.gfx_context;
+    let (width, height, _depth, _aa) = gfx.data.out.get_dimensions();
+    let texture = gfx.data.out.raw().get_texture().clone();
+    let data = read_texture(gfx, &texture, width, height)?;
+    let mut image_data = ImageData::from_rgba8(width as u32, height as u32, data)?;
+    // OpenGL's origin is at the bottom-left, so we flip the rows.
+    image_data.flip_vertical();
+    Ok(image_data)
+}
+
+/// Takes a screenshot with `screenshot()` and saves it as a PNG file
+/// at the given path in the user data directory.
+pub fn save_screenshot<P: AsRef<path::Path>>(ctx: &mut Context, path: P) -> GameResult<()> {
+    let image_data = screenshot(ctx)?;
+    image_data.save_png(ctx, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_image_data_size_check() {
+        assert!(ImageData::from_rgba8(2, 2, vec![0; 16]).is_ok());
+        assert!(ImageData::from_rgba8(2, 2, vec![0; 15]).is_err());
+        assert!(ImageData::from_rgba8(0, 0, vec![]).is_ok());
+    }
+
+    #[test]
+    fn test_check_region() {
+        assert!(check_region(4, 4, 1, 2, 3, 2, 24).is_ok());
+        assert!(check_region(4, 4, 2, 0, 3, 1, 12).is_err());
+        assert!(check_region(4, 4, 0, 0, 2, 2, 15).is_err());
+        // No wrapping around past the largest u16.
+        assert!(check_region(4, 4, u16::MAX, 0, 2, 1, 8).is_err());
+    }
+
+    #[test]
+    fn test_encode_bmp() {
+        // Two pixels high, so we can see the rows get flipped.
+        let bmp = encode_bmp(1, 2, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(bmp.len(), 14 + 108 + 8);
+        assert_eq!(&bmp[0..2], b"BM");
+        assert_eq!(bmp[2], bmp.len() as u8);
+        assert_eq!(bmp[10], 14 + 108);
+        assert_eq!(&bmp[122..], &[7, 6, 5, 8, 3, 2, 1, 4]);
+
+        assert!(encode_bmp(65536, 16384, &[]).is_err());
+    }
+
+    #[test]
+    fn test_image_data_editing() {
+        let red = Color::from((255, 0, 0, 255));
+        let blue = Color::from((0, 0, 255, 255));
+        let mut data = ImageData::new(3, 2);
+        data.fill(red);
+        data.set_pixel(0, 0, blue).unwrap();
+        assert!(data.set_pixel(3, 0, blue).is_err());
+        assert_eq!(data.get_pixel(0, 0), Some(blue));
+        assert_eq!(data.get_pixel(2, 1), Some(red));
+        assert_eq!(data.get_pixel(0, 2), None);
+
+        let mut flipped = data.clone();
+        flipped.flip_horizontal();
+        assert_eq!(flipped.get_pixel(2, 0), Some(blue));
+        flipped.flip_vertical();
+        assert_eq!(flipped.get_pixel(2, 1), Some(blue));
+        assert_eq!(flipped.get_pixel(2, 0), Some(red));
+
+        let corner = data.crop(0, 0, 2, 1).unwrap();
+        assert_eq!(corner.width(), 2);
+        assert_eq!(corner.get_pixel(0, 0), Some(blue));
+        assert_eq!(corner.get_pixel(1, 0), Some(red));
+        assert!(data.crop(2, 0, 2, 1).is_err());
+        assert!(data.crop(::std::u32::MAX, 0, 1, 1).is_err());
+
+        let mut canvas = ImageData::new(4, 4);
+        canvas.blit(&corner, 3, -1);
+        canvas.blit(&corner, 1, 2);
+        assert_eq!(canvas.get_pixel(3, 0), Some(Color::new(0.0, 0.0, 0.0, 0.0)));
+        assert_eq!(canvas.get_pixel(1, 2), Some(blue));
+        assert_eq!(canvas.get_pixel(2, 2), Some(red));
+        assert_eq!(canvas.get_pixel(3, 2), Some(Color::new(0.0, 0.0, 0.0, 0.0)));
+    }
+}
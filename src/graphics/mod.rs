@@ -9,13 +9,17 @@
 use std::fmt;
 use std::path;
 use std::convert::From;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::io::Read;
+use std::rc::{Rc, Weak};
 use std::u16;
 
 use sdl2;
 use image;
+use rusttype;
 use gfx;
+use gfx::memory::Typed;
 use gfx::texture;
 use gfx::traits::Device;
 use gfx::traits::FactoryExt;
@@ -28,33 +32,65 @@ use context::Context;
 use GameError;
 use GameResult;
 
+mod animation;
+mod atlas;
+mod bmfont;
+mod canvas;
+mod imagedata;
+mod shader;
+mod spritebatch;
 mod tessellation;
 mod text;
 mod types;
 
+pub use self::animation::*;
+pub use self::atlas::*;
+pub use self::canvas::*;
+pub use self::imagedata::*;
+pub use self::shader::*;
+pub use self::spritebatch::*;
 pub use self::text::*;
 pub use self::types::*;
 
 const GL_MAJOR_VERSION: u8 = 3;
 const GL_MINOR_VERSION: u8 = 2;
 
+/// The width and height of the glyph cache's texture to begin with.
+const GLYPH_CACHE_SIZE: u32 = 512;
+
+/// How big the glyph cache's texture may grow if the glyphs queued
+/// at once don't fit.
+const MAX_GLYPH_CACHE_SIZE: u32 = 4096;
+
+/// The `ShaderId` of the built-in shader that draws signed distance
+/// field `Text`; it's made right after the default shader.
+const SDF_SHADER: ShaderId = 1;
+
+/// The DPI assumed when the display can't tell us; matches the
+/// DPI `Font::default_font()` assumes.
+const DEFAULT_DPI: (f32, f32, f32) = (75.0, 75.0, 75.0);
+
 
 
 const QUAD_VERTS: [Vertex; 4] = [Vertex {
                                      pos: [-0.5, -0.5],
                                      uv: [0.0, 0.0],
+                                     color: [1.0, 1.0, 1.0, 1.0],
                                  },
                                  Vertex {
                                      pos: [0.5, -0.5],
                                      uv: [1.0, 0.0],
+                                     color: [1.0, 1.0, 1.0, 1.0],
                                  },
                                  Vertex {
                                      pos: [0.5, 0.5],
                                      uv: [1.0, 1.0],
+                                     color: [1.0, 1.0, 1.0, 1.0],
                                  },
                                  Vertex {
                                      pos: [-0.5, 0.5],
                                      uv: [0.0, 1.0],
+                                     color: [1.0, 1.0, 1.0, 1.0],
                                  }];
 
 const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
@@ -63,10 +99,13 @@ type ColorFormat = gfx::format::Srgba8;
 type DepthFormat = gfx::format::DepthStencil;
 
 gfx_defines!{
-    /// Internal structure containing vertex data.
+    /// A vertex of a `Mesh`: its position, texture coordinates from
+    /// 0 to 1 across the mesh's `Image`, and color, which the texture
+    /// is multiplied by.
     vertex Vertex {
         pos: [f32; 2] = "a_Pos",
         uv: [f32; 2] = "a_Uv",
+        color: [f32; 4] = "a_VertColor",
     }
 
     /// Internal structure containing global shader state.
@@ -76,25 +115,65 @@ gfx_defines!{
     }
 
     /// Internal structure containing values that are different for each rect.
-    constant RectProperties {
-        src: [f32; 4] = "u_Src",
-        dest: [f32; 2] = "u_Dest",
-        scale: [f32;2] = "u_Scale",
-        offset: [f32;2] = "u_Offset",
-        shear: [f32;2] = "u_Shear",
-        rotation: f32 = "u_Rotation",
+    /// These are fed to the shader as per-instance vertex attributes,
+    /// so that many rects can be drawn with a single draw call.
+    vertex RectProperties {
+        src: [f32; 4] = "a_Src",
+        dest: [f32; 2] = "a_Dest",
+        scale: [f32;2] = "a_Scale",
+        offset: [f32;2] = "a_Offset",
+        shear: [f32;2] = "a_Shear",
+        rotation: f32 = "a_Rotation",
+        color: [f32;4] = "a_Color",
     }
 
     pipeline pipe {
         vbuf: gfx::VertexBuffer<Vertex> = (),
         tex: gfx::TextureSampler<[f32; 4]> = "t_Texture",
         globals: gfx::ConstantBuffer<Globals> = "Globals",
-        rect_properties: gfx::ConstantBuffer<RectProperties> = "RectProperties",
+        rect_instance_properties: gfx::InstanceBuffer<RectProperties> = (),
+        user_consts: gfx::RawConstantBuffer = "Consts",
+        scissor: gfx::Scissor = (),
+        stencil: gfx::StencilTarget<DepthFormat> = StencilState::default().into(),
         out: gfx::BlendTarget<ColorFormat> =
           ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
     }
 }
 
+/// How drawing uses the stencil buffer, which, like the blend mode,
+/// is baked into the pipeline state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum StencilState {
+    /// Only draws where the stored stencil value passes the comparison.
+    Test(CompareMode),
+    /// Writes to the stencil buffer instead of the color buffer.
+    Write,
+}
+
+impl Default for StencilState {
+    fn default() -> Self {
+        StencilState::Test(CompareMode::Always)
+    }
+}
+
+impl From<StencilState> for gfx::state::Stencil {
+    fn from(state: StencilState) -> Self {
+        use gfx::state::{Comparison, Stencil, StencilOp};
+        match state {
+            StencilState::Test(mode) => {
+                Stencil::new(mode.into(),
+                             0xFF,
+                             (StencilOp::Keep, StencilOp::Keep, StencilOp::Keep))
+            }
+            StencilState::Write => {
+                Stencil::new(Comparison::Always,
+                             0xFF,
+                             (StencilOp::Keep, StencilOp::Keep, StencilOp::Replace))
+            }
+        }
+    }
+}
+
 impl Default for RectProperties {
     fn default() -> Self {
         RectProperties {
@@ -104,6 +183,7 @@ impl Default for RectProperties {
             offset: [0.0, 0.0],
             shear: [0.0, 0.0],
             rotation: 0.0,
+            color: types::WHITE.into(),
         }
     }
 }
@@ -117,6 +197,7 @@ impl From<DrawParam> for RectProperties {
             offset: p.offset.into(),
             shear: p.shear.into(),
             rotation: p.rotation,
+            color: p.color.unwrap_or(types::WHITE).into(),
         }
     }
 }
@@ -152,6 +233,119 @@ impl<R> SamplerCache<R>
     }
 }
 
+/// A glyph queued with `queue_text()`, waiting to be drawn.
+struct QueuedGlyph {
+    font_id: usize,
+    glyph: rusttype::PositionedGlyph<'static>,
+    color: [f32; 4],
+}
+
+/// The cache that text queued with `queue_text()` is drawn through.
+/// Each glyph is rasterized once into a texture shared by all fonts,
+/// and drawn out of that from then on, so changing text doesn't need
+/// any new textures.
+struct GlyphCache {
+    cache: rusttype::gpu_cache::Cache,
+    image: Image,
+    size: u32,
+    queue: Vec<QueuedGlyph>,
+    // Grown as needed to fit all the queued glyphs.
+    vertex_buffer: Option<gfx::handle::Buffer<gfx_device_gl::Resources, Vertex>>,
+}
+
+impl GlyphCache {
+    fn new(factory: &mut gfx_device_gl::Factory,
+           sampler_info: &texture::SamplerInfo,
+           size: u32)
+           -> GameResult<GlyphCache> {
+        let blank = vec![0; size as usize * size as usize * 4];
+        let image = Image::make_raw(factory, sampler_info, size as u16, size as u16, &blank)?;
+        Ok(GlyphCache {
+               cache: rusttype::gpu_cache::Cache::new(size, size, 0.1, 0.1),
+               image: image,
+               size: size,
+               queue: Vec::new(),
+               vertex_buffer: None,
+           })
+    }
+}
+
+/// A compiled shader program, the pipeline states built from it
+/// for each blend mode and stencil state it's been used with, and
+/// the buffer holding its user-defined constants.
+struct ShaderProgram<R>
+    where R: gfx::Resources
+{
+    program: gfx::handle::Program<R>,
+    consts_name: String,
+    consts: gfx::handle::RawBuffer<R>,
+    psos: HashMap<(BlendMode, StencilState), gfx::PipelineState<R, pipe::Meta>>,
+    // The `Shader` this program belongs to, if it's a user-defined one;
+    // once that's dropped the program can be freed.
+    owner: Option<Weak<()>>,
+}
+
+impl<R> ShaderProgram<R>
+    where R: gfx::Resources
+{
+    /// Compiles the given shader sources and links them into a program,
+    /// with the uniform block named `consts_name` bound to `consts`.
+    ///
+    /// The pipeline state for the default blend mode is created right
+    /// away, so that errors in the shader show up here rather than
+    /// the first time something is drawn with it.
+    fn new<F>(factory: &mut F,
+              vertex_source: &[u8],
+              pixel_source: &[u8],
+              consts_name: &str,
+              consts: gfx::handle::RawBuffer<R>)
+              -> GameResult<ShaderProgram<R>>
+        where F: gfx::Factory<R>
+    {
+        let program = factory.link_program(vertex_source, pixel_source)?;
+        let mut shader = ShaderProgram {
+            program: program,
+            consts_name: consts_name.to_owned(),
+            consts: consts,
+            psos: HashMap::new(),
+            owner: None,
+        };
+        shader.get_or_insert_pso(BlendMode::default(), StencilState::default(), factory)?;
+        Ok(shader)
+    }
+
+    /// Returns the pipeline state for drawing with the given blend mode
+    /// and stencil state, creating it if necessary.
+    fn get_or_insert_pso<F>(&mut self,
+                            mode: BlendMode,
+                            stencil: StencilState,
+                            factory: &mut F)
+                            -> GameResult<&gfx::PipelineState<R, pipe::Meta>>
+        where F: gfx::Factory<R>
+    {
+        let key = (mode, stencil);
+        if !self.psos.contains_key(&key) {
+            // Nothing shows up on screen while writing the stencil.
+            let color_mask = match stencil {
+                StencilState::Write => gfx::state::ColorMask::empty(),
+                StencilState::Test(_) => gfx::state::MASK_ALL,
+            };
+            let init = pipe::Init {
+                out: ("Target0", color_mask, mode.into()),
+                stencil: stencil.into(),
+                user_consts: &self.consts_name,
+                ..pipe::new()
+            };
+            let pso = factory.create_pipeline_from_program(&self.program,
+                                              gfx::Primitive::TriangleList,
+                                              gfx::state::Rasterizer::new_fill(),
+                                              init)?;
+            self.psos.insert(key, pso);
+        }
+        Ok(&self.psos[&key])
+    }
+}
+
 /// A structure that contains graphics state.
 /// For instance, background and foreground colors,
 /// window info, DPI, rendering pipeline state, etc.
@@ -167,9 +361,12 @@ pub struct GraphicsContextGeneric<R, F, C, D>
 {
     background_color: Color,
     shader_globals: Globals,
+    projection: [[f32; 4]; 4],
+    transform_stack: Vec<Transform>,
     white_image: Image,
-    line_width: f32,
+    stroke_options: StrokeOptions,
     point_size: f32,
+    tolerance: f32,
     screen_rect: Rect,
     dpi: (f32, f32, f32),
 
@@ -179,16 +376,22 @@ pub struct GraphicsContextGeneric<R, F, C, D>
     device: Box<D>,
     factory: Box<F>,
     encoder: gfx::Encoder<R, C>,
-    // color_view: gfx::handle::RenderTargetView<R, gfx::format::Srgba8>,
-    #[allow(dead_code)]
+    screen_render_target: gfx::handle::RenderTargetView<R, ColorFormat>,
     depth_view: gfx::handle::DepthStencilView<R, gfx::format::DepthStencil>,
 
-    pso: gfx::PipelineState<R, pipe::Meta>,
+    // Slots of freed user-defined shaders are `None` until reused.
+    shaders: Vec<Option<ShaderProgram<R>>>,
+    current_shader: Rc<Cell<ShaderId>>,
+    blend_mode: BlendMode,
+    scissor: Option<Rect>,
+    stencil_test: Option<(CompareMode, u8)>,
+    writing_stencil: bool,
     data: pipe::Data<R>,
     quad_slice: gfx::Slice<R>,
     quad_vertex_buffer: gfx::handle::Buffer<R, Vertex>,
     default_sampler_info: texture::SamplerInfo,
     samplers: SamplerCache<R>,
+    glyph_cache: GlyphCache,
 }
 
 impl<R, F, C, D> fmt::Debug for GraphicsContextGeneric<R, F, C, D>
@@ -246,7 +449,8 @@ impl GraphicsContext {
                window_title: &str,
                screen_width: u32,
                screen_height: u32,
-               vsync: bool)
+               vsync: bool,
+               hidden: bool)
                -> GameResult<GraphicsContext> {
         // WINDOW SETUP
         let gl = video.gl_attr();
@@ -256,7 +460,10 @@ impl GraphicsContext {
         gl.set_green_size(5);
         gl.set_blue_size(5);
         gl.set_alpha_size(8);
-        let window_builder = video.window(window_title, screen_width, screen_height);
+        let mut window_builder = video.window(window_title, screen_width, screen_height);
+        if hidden {
+            window_builder.hidden();
+        }
         let (window, gl_context, device, mut factory, color_view, depth_view) =
             gfx_window_sdl::init(window_builder)?;
 
@@ -265,21 +472,41 @@ impl GraphicsContext {
         video.gl_set_swap_interval(vsync_int);
 
         let display_index = window.display_index()?;
-        let dpi = window.subsystem().display_dpi(display_index)?;
+        let dpi = match window.subsystem().display_dpi(display_index) {
+            Ok(dpi) => dpi,
+            // Offscreen video drivers may have no idea what DPI
+            // their "display" is, so we just make one up.
+            Err(_) if hidden => DEFAULT_DPI,
+            Err(e) => return Err(GameError::from(e)),
+        };
 
         // GFX SETUP
         let encoder: gfx::Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer> =
             factory.create_command_buffer().into();
             
-        let pso = factory
-            .create_pipeline_simple(include_bytes!("shader/basic_150.glslv"),
-                                    include_bytes!("shader/basic_150.glslf"),
-                                    pipe::new())?;
-
-        let (quad_vertex_buffer, quad_slice) =
+        // The default shader has no user constants, but the pipeline
+        // data still needs some buffer to hold on to for them.
+        let user_consts = factory.create_constant_buffer::<Globals>(1).raw().clone();
+        let default_shader = ShaderProgram::new(&mut factory,
+                                                include_bytes!("shader/basic_150.glslv"),
+                                                include_bytes!("shader/basic_150.glslf"),
+                                                "Consts",
+                                                user_consts.clone())?;
+        let sdf_shader = ShaderProgram::new(&mut factory,
+                                            include_bytes!("shader/basic_150.glslv"),
+                                            include_bytes!("shader/sdf_150.glslf"),
+                                            "Consts",
+                                            user_consts.clone())?;
+
+        let (quad_vertex_buffer, mut quad_slice) =
             factory.create_vertex_buffer_with_slice(&QUAD_VERTS, &QUAD_INDICES[..]);
+        // Everything is drawn instanced; normal draws just have one instance.
+        quad_slice.instances = Some((1, 0));
 
-        let rect_props = factory.create_constant_buffer(1);
+        let rect_inst_props = factory.create_buffer(1,
+                                                    gfx::buffer::Role::Vertex,
+                                                    gfx::memory::Usage::Dynamic,
+                                                    gfx::TRANSFER_DST)?;
         let globals_buffer = factory.create_constant_buffer(1);
         let mut samplers: SamplerCache<gfx_device_gl::Resources> = SamplerCache::new();
         let sampler_info = texture::SamplerInfo::new(texture::FilterMethod::Bilinear,
@@ -288,13 +515,22 @@ impl GraphicsContext {
         let white_image =
             Image::make_raw(&mut factory, &sampler_info, 1, 1, &[255, 255, 255, 255])?;
         let texture = white_image.texture.clone();
+        let glyph_cache = GlyphCache::new(&mut factory, &sampler_info, GLYPH_CACHE_SIZE)?;
 
         let data = pipe::Data {
             vbuf: quad_vertex_buffer.clone(),
             tex: (texture, sampler),
-            rect_properties: rect_props,
+            rect_instance_properties: rect_inst_props,
+            user_consts: user_consts,
             globals: globals_buffer,
-            out: color_view,
+            scissor: gfx::Rect {
+                x: 0,
+                y: 0,
+                w: screen_width as u16,
+                h: screen_height as u16,
+            },
+            stencil: (depth_view.clone(), (0, 0)),
+            out: color_view.clone(),
         };
 
         // Set initial uniform values
@@ -302,16 +538,20 @@ impl GraphicsContext {
         let right = screen_width as f32;
         let top = 0.0;
         let bottom = screen_height as f32;
+        let projection = ortho(left, right, top, bottom, 1.0, -1.0);
         let globals = Globals {
-            transform: ortho(left, right, top, bottom, 1.0, -1.0),
+            transform: projection,
             color: types::WHITE.into(),
         };
 
         let mut gfx = GraphicsContext {
             background_color: Color::new(0.1, 0.2, 0.3, 1.0),
             shader_globals: globals,
-            line_width: 1.0,
+            projection: projection,
+            transform_stack: vec![Transform::identity()],
+            stroke_options: StrokeOptions::default(),
             point_size: 1.0,
+            tolerance: tessellation::DEFAULT_TOLERANCE,
             white_image: white_image,
             screen_rect: Rect::new(left, bottom, (right - left), (top - bottom)),
             dpi: dpi,
@@ -321,29 +561,111 @@ impl GraphicsContext {
             device: Box::new(device),
             factory: Box::new(factory),
             encoder: encoder,
+            screen_render_target: color_view,
             depth_view: depth_view,
 
-            pso: pso,
+            shaders: vec![Some(default_shader), Some(sdf_shader)],
+            current_shader: Rc::new(Cell::new(DEFAULT_SHADER)),
+            blend_mode: BlendMode::default(),
+            scissor: None,
+            stencil_test: None,
+            writing_stencil: false,
             data: data,
             quad_slice: quad_slice,
             quad_vertex_buffer: quad_vertex_buffer,
             default_sampler_info: sampler_info,
             samplers: samplers,
+            glyph_cache: glyph_cache,
         };
         gfx.update_globals()?;
         Ok(gfx)
     }
 
+    /// Returns the current transform, from the top of the transform stack.
+    fn get_transform(&self) -> Transform {
+        *self.transform_stack
+             .last()
+             .expect("Transform stack is empty; should never happen!")
+    }
+
+    /// Sets the current transform on the top of the transform stack,
+    /// and recalculates the shader's transform matrix from it.
+    fn set_transform(&mut self, transform: Transform) -> GameResult<()> {
+        if let Some(top) = self.transform_stack.last_mut() {
+            *top = transform;
+        }
+        self.shader_globals.transform = matrix_mul(&self.projection, &transform.to_matrix4());
+        self.update_globals()
+    }
+
+    /// Frees the programs of `Shader`s that have been dropped, so that
+    /// their slots can be reused, going back to the default shader if
+    /// the one in use was among them.
+    fn free_dropped_shaders(&mut self) {
+        free_dropped_slots(&mut self.shaders, |program| program.owner.as_ref());
+        if let Some(&None) = self.shaders.get(self.current_shader.get()) {
+            self.current_shader.set(DEFAULT_SHADER);
+        }
+    }
+
     fn update_globals(&mut self) -> GameResult<()> {
         self.encoder
             .update_buffer(&self.data.globals, &[self.shader_globals], 0)?;
         Ok(())
     }
 
+    /// Draws the given slice (or the unit quad, if `None`) with the current
+    /// pipeline data, using whatever shader is currently in use.
+    fn draw(&mut self, slice: Option<&gfx::Slice<gfx_device_gl::Resources>>) -> GameResult<()> {
+        // The render target or screen coordinates may have changed
+        // since the scissor was set, so it's worked out fresh each time.
+        let (width, height, _depth, _aa) = self.data.out.get_dimensions();
+        self.data.scissor = match self.scissor {
+            Some(rect) => scissor_pixels(rect, self.screen_rect, width, height),
+            None => {
+                gfx::Rect {
+                    x: 0,
+                    y: 0,
+                    w: width,
+                    h: height,
+                }
+            }
+        };
+        let (stencil, value) = match (self.writing_stencil, self.stencil_test) {
+            (true, _) => (StencilState::Write, 1),
+            (false, Some((mode, value))) => (StencilState::Test(mode), value),
+            (false, None) => (StencilState::default(), 0),
+        };
+        self.data.stencil.1 = (value, value);
+
+        let id = match self.shaders.get(self.current_shader.get()) {
+            Some(&Some(_)) => self.current_shader.get(),
+            _ => DEFAULT_SHADER,
+        };
+        let shader = self.shaders[id]
+            .as_mut()
+            .expect("Default shader is missing; should never happen!");
+        self.data.user_consts = shader.consts.clone();
+        let pso = shader.get_or_insert_pso(self.blend_mode, stencil, self.factory.as_mut())?;
+        let slice = slice.unwrap_or(&self.quad_slice);
+        self.encoder.draw(slice, pso, &self.data);
+        Ok(())
+    }
+
+    /// Converts a `DrawParam` to the `RectProperties` to draw with,
+    /// using the current foreground color if the `DrawParam` has none.
+    fn rect_properties(&self, draw_params: DrawParam) -> RectProperties {
+        let mut properties: RectProperties = draw_params.into();
+        if draw_params.color.is_none() {
+            properties.color = self.shader_globals.color;
+        }
+        properties
+    }
+
     fn update_rect_properties(&mut self, draw_params: DrawParam) -> GameResult<()> {
-        let properties = draw_params.into();
+        let properties = self.rect_properties(draw_params);
         self.encoder
-            .update_buffer(&self.data.rect_properties, &[properties], 0)?;
+            .update_buffer(&self.data.rect_instance_properties, &[properties], 0)?;
         Ok(())
     }
 
@@ -379,6 +701,65 @@ impl GraphicsContext {
 }
 
 
+/// Empties the slots whose owner, as `owner` finds it, is gone.  Slots
+/// with no owner are never emptied.  Anything still holding a strong
+/// reference to an owner, like a `ShaderLock` that may go back to its
+/// shader, keeps that slot from being emptied and reused.
+fn free_dropped_slots<T, F>(slots: &mut [Option<T>], owner: F)
+    where F: Fn(&T) -> Option<&Weak<()>>
+{
+    for slot in slots {
+        let dropped = match *slot {
+            Some(ref item) => owner(item).map_or(false, |o| o.upgrade().is_none()),
+            None => false,
+        };
+        if dropped {
+            *slot = None;
+        }
+    }
+}
+
+/// Converts a rect in screen coordinates to the pixels of a render
+/// target of the given size, clamped to fit within it.
+///
+/// OpenGL puts the origin of the target at its bottom-left, and the
+/// screen rect is stored as `(left, bottom, right - left, top - bottom)`,
+/// so the fraction of the way up the target is just relative to it.
+fn scissor_pixels(rect: Rect, screen_rect: Rect, width: u16, height: u16) -> gfx::Rect {
+    let (width, height) = (width as f32, height as f32);
+    let to_x = |x: f32| ((x - screen_rect.x) / screen_rect.w * width).round().max(0.0).min(width);
+    let to_y = |y: f32| ((y - screen_rect.y) / screen_rect.h * height).round().max(0.0).min(height);
+    let (x1, x2) = (to_x(rect.x), to_x(rect.x + rect.w));
+    let (y1, y2) = (to_y(rect.y), to_y(rect.y + rect.h));
+    gfx::Rect {
+        x: x1.min(x2) as u16,
+        y: y1.min(y2) as u16,
+        w: (x2 - x1).abs() as u16,
+        h: (y2 - y1).abs() as u16,
+    }
+}
+
+/// Checks that RGBA data makes a texture of the given size: that it
+/// isn't empty, and that there's exactly enough data to fill it.
+fn check_texture_data(width: u16, height: u16, rgba: &[u8]) -> GameResult<()> {
+    if width == 0 || height == 0 {
+        let msg = format!("Tried to create a texture of size {}x{}, each dimension must be >0",
+                          width,
+                          height);
+        return Err(GameError::ResourceLoadError(msg));
+    }
+    let expected = width as usize * height as usize * 4;
+    if rgba.len() != expected {
+        let msg = format!("Image data for a {}x{} image should be {} bytes, got {}",
+                          width,
+                          height,
+                          expected,
+                          rgba.len());
+        return Err(GameError::ResourceLoadError(msg));
+    }
+    Ok(())
+}
+
 /// Creates an orthographic projection matrix.
 /// 
 /// Rather than create a dependency on cgmath or nalgebra for this one function,
@@ -410,6 +791,40 @@ fn ortho(left: f32, right: f32, top: f32, bottom: f32, far: f32, near: f32) -> [
      [c0r3, c1r3, c2r3, c3r3]]
 }
 
+/// Multiplies two matrices laid out the same way `ortho()` lays them out,
+/// so that the result does the transformation of `b` followed by that of `a`.
+fn matrix_mul(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+/// Creates a matrix that applies the same scale, shear, offset, rotation
+/// and translation to a point that the vertex shader applies with a
+/// `DrawParam`.  The `src` field is ignored.
+fn draw_param_matrix(param: &DrawParam) -> [[f32; 4]; 4] {
+    let (sin, cos) = param.rotation.sin_cos();
+    let (sx, sy) = (param.scale.x, param.scale.y);
+    let (shx, shy) = (param.shear.x, param.shear.y);
+    let (ox, oy) = (param.offset.x, param.offset.y);
+
+    let m00 = cos * sx - sin * shy * sx;
+    let m01 = cos * shx * sy - sin * sy;
+    let m10 = sin * sx + cos * shy * sx;
+    let m11 = sin * shx * sy + cos * sy;
+    let tx = cos * ox - sin * oy + param.dest.x;
+    let ty = sin * ox + cos * oy + param.dest.y;
+
+    [[m00, m01, 0.0, tx],
+     [m10, m11, 0.0, ty],
+     [0.0, 0.0, 1.0, 0.0],
+     [0.0, 0.0, 0.0, 1.0]]
+}
+
 // **********************************************************************
 // DRAWING
 // **********************************************************************
@@ -420,6 +835,7 @@ pub fn clear(ctx: &mut Context) {
     let gfx = &mut ctx.gfx_context;
     gfx.encoder
         .clear(&gfx.data.out, gfx.background_color.into());
+    gfx.encoder.clear_stencil(&gfx.data.stencil.0, 0);
 }
 
 /// Draws the given `Drawable` object to the screen by calling its
@@ -444,21 +860,24 @@ pub fn present(ctx: &mut Context) {
     // thing is a bigger hurdle, so this is fine for now.
     gfx.encoder.flush(&mut *gfx.device);
     gfx.window.gl_swap_window();
+    gfx.free_dropped_shaders();
     gfx.device.cleanup();
 }
 
-// Draw an arc.
-// Punting on this until later.
-// pub fn arc(_ctx: &mut Context,
-//            _mode: DrawMode,
-//            _point: Point,
-//            _radius: f32,
-//            _angle1: f32,
-//            _angle2: f32,
-//            _segments: u32)
-//            -> GameResult<()> {
-//     unimplemented!();
-// }
+/// Draw an arc of a circle, from `angle1` to `angle2` in radians.
+#[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+pub fn arc(ctx: &mut Context,
+           mode: DrawMode,
+           arc_mode: ArcMode,
+           point: Point,
+           radius: f32,
+           angle1: f32,
+           angle2: f32,
+           segments: u32)
+           -> GameResult<()> {
+    let m = Mesh::new_arc(ctx, mode, arc_mode, point, radius, angle1, angle2, segments)?;
+    m.draw(ctx, Point::default(), 0.0)
+}
 
 /// Draw a circle.
 pub fn circle(ctx: &mut Context,
@@ -483,10 +902,11 @@ pub fn ellipse(ctx: &mut Context,
     m.draw(ctx, Point::default(), 0.0)
 }
 
-/// Draws a line of one or more connected segments.
+/// Draws a line of one or more connected segments,
+/// using the current `StrokeOptions`.
 pub fn line(ctx: &mut Context, points: &[Point]) -> GameResult<()> {
-    let w = ctx.gfx_context.line_width;
-    let m = Mesh::new_line(ctx, points, w)?;
+    let options = ctx.gfx_context.stroke_options.clone();
+    let m = Mesh::new_line(ctx, points, options)?;
     m.draw(ctx, Point::default(), 0.0)
 }
 
@@ -500,10 +920,11 @@ pub fn points(ctx: &mut Context, points: &[Point]) -> GameResult<()> {
     Ok(())
 }
 
-/// Draws a closed polygon
+/// Draws a closed polygon.
+/// Stroked polygons use the current `StrokeOptions`.
 pub fn polygon(ctx: &mut Context, mode: DrawMode, vertices: &[Point]) -> GameResult<()> {
-    let w = ctx.gfx_context.line_width;
-    let m = Mesh::new_polygon(ctx, mode, vertices, w)?;
+    let options = ctx.gfx_context.stroke_options.clone();
+    let m = Mesh::new_polygon(ctx, mode, vertices, options)?;
     m.draw(ctx, Point::default(), 0.0)
 }
 
@@ -520,7 +941,18 @@ pub fn polygon(ctx: &mut Context, mode: DrawMode, vertices: &[Point]) -> GameRes
 // }
 
 
+/// Draws a rectangle with rounded corners of the given radius.
+pub fn rounded_rectangle(ctx: &mut Context,
+                         mode: DrawMode,
+                         rect: Rect,
+                         radius: f32)
+                         -> GameResult<()> {
+    let m = Mesh::new_rounded_rectangle(ctx, mode, rect, radius)?;
+    m.draw(ctx, Point::default(), 0.0)
+}
+
 /// Draws a rectangle.
+/// Stroked rectangles use the current `StrokeOptions`.
 pub fn rectangle(ctx: &mut Context, mode: DrawMode, rect: Rect) -> GameResult<()> {
     let x = rect.x;
     let y = rect.y;
@@ -546,6 +978,11 @@ pub fn get_background_color(ctx: &Context) -> Color {
     ctx.gfx_context.background_color
 }
 
+/// Returns the current blend mode.
+pub fn get_blend_mode(ctx: &Context) -> BlendMode {
+    ctx.gfx_context.blend_mode
+}
+
 /// Returns the current foreground color.
 pub fn get_color(ctx: &Context) -> Color {
     ctx.gfx_context.shader_globals.color.into()
@@ -558,9 +995,20 @@ pub fn get_default_filter(ctx: &Context) -> FilterMode {
 }
 
 
+/// Get how far, in pixels, curved shapes may be off from the
+/// straight line segments they're approximated with.
+pub fn get_tolerance(ctx: &Context) -> f32 {
+    ctx.gfx_context.tolerance
+}
+
 /// Get the current width for drawing lines and stroked polygons.
 pub fn get_line_width(ctx: &Context) -> f32 {
-    ctx.gfx_context.line_width
+    ctx.gfx_context.stroke_options.width
+}
+
+/// Get the current options for drawing lines and stroked shapes.
+pub fn get_stroke_options(ctx: &Context) -> &StrokeOptions {
+    &ctx.gfx_context.stroke_options
 }
 
 
@@ -596,8 +1044,70 @@ pub fn set_background_color(ctx: &mut Context, color: Color) {
     ctx.gfx_context.background_color = color;
 }
 
-/// Sets the foreground color, which will be used for drawing
-/// rectangles, lines, etc.  Default: white.
+/// Sets the blend mode used for all subsequent drawing.  Default: `BlendMode::Alpha`.
+///
+/// The pipeline state for each blend mode is created the first
+/// time it is drawn with, and kept around after that.
+pub fn set_blend_mode(ctx: &mut Context, mode: BlendMode) {
+    ctx.gfx_context.blend_mode = mode;
+}
+
+/// Sets a rectangle, in screen coordinates, outside of which nothing
+/// gets drawn.  `None` turns it off, letting things be drawn anywhere.
+///
+/// The transform set with `push_transform()` and friends does not
+/// apply to it.
+pub fn set_scissor(ctx: &mut Context, rect: Option<Rect>) {
+    ctx.gfx_context.scissor = rect;
+}
+
+/// Gets the current scissor rectangle, if any.
+pub fn get_scissor(ctx: &Context) -> Option<Rect> {
+    ctx.gfx_context.scissor
+}
+
+/// Draws a mask into the stencil buffer, for use with `set_stencil_test()`.
+///
+/// The stencil buffer is cleared, then everything drawn by `draw_mask`
+/// sets the stencil value to 1 where it covers, without drawing anything
+/// to the screen.  Note that this goes by the shapes being drawn, so
+/// even transparent parts of an `Image` count; a `Mesh` usually makes
+/// a better mask.
+///
+/// ```rust,ignore
+/// graphics::stencil(ctx, |ctx| graphics::circle(ctx, DrawMode::Fill, center, 100.0, 32))?;
+/// graphics::set_stencil_test(ctx, Some((CompareMode::Equal, 1)));
+/// // Only shows up inside the circle.
+/// graphics::draw(ctx, &image, Point::zero(), 0.0)?;
+/// graphics::set_stencil_test(ctx, None);
+/// ```
+pub fn stencil<F>(ctx: &mut Context, draw_mask: F) -> GameResult<()>
+    where F: FnOnce(&mut Context) -> GameResult<()>
+{
+    {
+        let gfx = &mut ctx.gfx_context;
+        gfx.encoder.clear_stencil(&gfx.data.stencil.0, 0);
+        gfx.writing_stencil = true;
+    }
+    let result = draw_mask(ctx);
+    ctx.gfx_context.writing_stencil = false;
+    result
+}
+
+/// Sets the stencil test for everything drawn from now on; things are
+/// only drawn where the value in the stencil buffer passes comparison
+/// with the given value.  `None` turns the test off.
+pub fn set_stencil_test(ctx: &mut Context, test: Option<(CompareMode, u8)>) {
+    ctx.gfx_context.stencil_test = test;
+}
+
+/// Gets the current stencil test, if any.
+pub fn get_stencil_test(ctx: &Context) -> Option<(CompareMode, u8)> {
+    ctx.gfx_context.stencil_test
+}
+
+/// Sets the foreground color, which everything drawn without
+/// a `DrawParam` `color` is tinted with.  Default: white.
 pub fn set_color(ctx: &mut Context, color: Color) -> GameResult<()> {
     let gfx = &mut ctx.gfx_context;
     gfx.shader_globals.color = color.into();
@@ -617,9 +1127,24 @@ pub fn set_default_filter(ctx: &mut Context, mode: FilterMode) {
     gfx.default_sampler_info = sampler_info;
 }
 
+/// Set how far, in pixels, curved shapes such as rounded rectangles
+/// and bezier curves may be off from the straight line segments they're
+/// approximated with.  Smaller values look smoother but make more
+/// triangles.  The default is 0.5.
+pub fn set_tolerance(ctx: &mut Context, tolerance: f32) {
+    ctx.gfx_context.tolerance = tolerance;
+}
+
 /// Set the current width for drawing lines and stroked polygons.
 pub fn set_line_width(ctx: &mut Context, width: f32) {
-    ctx.gfx_context.line_width = width;
+    ctx.gfx_context.stroke_options.width = width;
+}
+
+/// Set the current options for drawing lines and stroked shapes:
+/// their width, joins, caps and dashes.  These are used by `line()`,
+/// `polygon()`, `rectangle()` and the other shape-drawing functions.
+pub fn set_stroke_options(ctx: &mut Context, options: StrokeOptions) {
+    ctx.gfx_context.stroke_options = options;
 }
 
 /// Set the current size for drawing points.
@@ -642,8 +1167,114 @@ pub fn set_screen_coordinates(context: &mut Context,
                               -> GameResult<()> {
     let gfx = &mut context.gfx_context;
     gfx.screen_rect = Rect::new(left, bottom, (right - left), (top - bottom));
-    gfx.shader_globals.transform = ortho(left, right, top, bottom, 1.0, -1.0);
-    gfx.update_globals()
+    gfx.projection = ortho(left, right, top, bottom, 1.0, -1.0);
+    let transform = gfx.get_transform();
+    gfx.set_transform(transform)
+}
+
+/// Saves the current transform on the transform stack, so that it
+/// can be brought back by `pop_transform()`.
+///
+/// The transform starts out as the identity, and changing it with
+/// `apply_transform()` and friends lets you move, rotate and scale
+/// everything drawn afterwards without touching the `DrawParam`s,
+/// on top of the coordinate system set by `set_screen_coordinates()`.
+/// For instance, a scrolling camera or hierarchical drawing.
+pub fn push_transform(ctx: &mut Context) {
+    let gfx = &mut ctx.gfx_context;
+    let transform = gfx.get_transform();
+    gfx.transform_stack.push(transform);
+}
+
+/// Restores the transform saved by the last call to `push_transform()`.
+///
+/// Returns an error if there is no matching `push_transform()`.
+pub fn pop_transform(ctx: &mut Context) -> GameResult<()> {
+    let gfx = &mut ctx.gfx_context;
+    if gfx.transform_stack.len() < 2 {
+        let msg = String::from("pop_transform() called without a matching push_transform()");
+        return Err(GameError::RenderError(msg));
+    }
+    gfx.transform_stack.pop();
+    let transform = gfx.get_transform();
+    gfx.set_transform(transform)
+}
+
+/// Combines the given transform with the current one.  It is applied
+/// to things being drawn before the current transform is, so for
+/// instance applying a rotation then a translation moves things in
+/// the rotated direction.
+pub fn apply_transform(ctx: &mut Context, transform: Transform) -> GameResult<()> {
+    let gfx = &mut ctx.gfx_context;
+    let new_transform = gfx.get_transform() * transform;
+    gfx.set_transform(new_transform)
+}
+
+/// Moves everything drawn from now on by the given amount.
+/// Shortcut for `apply_transform()` with `Transform::translate()`.
+pub fn translate(ctx: &mut Context, x: f32, y: f32) -> GameResult<()> {
+    apply_transform(ctx, Transform::translate(x, y))
+}
+
+/// Rotates everything drawn from now on around the origin.
+/// Shortcut for `apply_transform()` with `Transform::rotate()`.
+pub fn rotate(ctx: &mut Context, angle: f32) -> GameResult<()> {
+    apply_transform(ctx, Transform::rotate(angle))
+}
+
+/// Scales everything drawn from now on.
+/// Shortcut for `apply_transform()` with `Transform::scale()`.
+pub fn scale(ctx: &mut Context, x: f32, y: f32) -> GameResult<()> {
+    apply_transform(ctx, Transform::scale(x, y))
+}
+
+/// Resets the current transform to the identity.
+/// Does not change the transform stack otherwise.
+pub fn origin(ctx: &mut Context) -> GameResult<()> {
+    ctx.gfx_context.set_transform(Transform::identity())
+}
+
+/// Returns the current transform.
+pub fn get_transform(ctx: &Context) -> Transform {
+    ctx.gfx_context.get_transform()
+}
+
+/// Replaces the current transform with the given one.
+pub fn set_transform(ctx: &mut Context, transform: Transform) -> GameResult<()> {
+    ctx.gfx_context.set_transform(transform)
+}
+
+/// Returns the transform taking a point in the current coordinate system,
+/// with the current transform applied, to a position in the window.
+fn world_to_screen_transform(ctx: &Context) -> Transform {
+    let gfx = &ctx.gfx_context;
+    let (width, height) = gfx.window.size();
+    let (width, height) = (width as f32, height as f32);
+    // From OpenGL's -1 to 1 coordinates with Y going up,
+    // to window coordinates with Y going down.
+    let viewport = Transform::from_matrix3([[width / 2.0, 0.0, width / 2.0],
+                                            [0.0, -height / 2.0, height / 2.0],
+                                            [0.0, 0.0, 1.0]]);
+    viewport * Transform::from_matrix4(gfx.projection) * gfx.get_transform()
+}
+
+/// Converts a point in the current coordinate system, with the current
+/// transform applied, to a position in the window such as the one given
+/// by mouse events.
+pub fn world_to_screen(ctx: &Context, point: Point) -> Point {
+    world_to_screen_transform(ctx).transform_point(point)
+}
+
+/// Converts a position in the window, such as the one given by mouse
+/// events, to a point in the current coordinate system with the current
+/// transform applied.  Handy for figuring out what the mouse is pointing at.
+///
+/// Returns `None` if the transforms in use can't be undone, for instance
+/// because they scale everything down to nothing.
+pub fn screen_to_world(ctx: &Context, point: Point) -> Option<Point> {
+    world_to_screen_transform(ctx)
+        .inverse()
+        .map(|t| t.transform_point(point))
 }
 
 // **********************************************************************
@@ -660,6 +1291,8 @@ pub fn set_screen_coordinates(context: &mut Context,
 /// * `scale` - x/y scale factors expressed as a `Point`.
 /// * `offset` - specifies an offset from the center for transform operations like scale/rotation.
 /// * `shear` - x/y shear factors expressed as a `Point`.
+/// * `color` - a color to tint the drawable with, multiplying its own colors.
+///    Defaults to the current foreground color set with `set_color()` if `None`.
 ///
 /// This struct implements the `Default` trait, so you can just do:
 ///
@@ -672,6 +1305,7 @@ pub struct DrawParam {
     pub scale: Point,
     pub offset: Point,
     pub shear: Point,
+    pub color: Option<Color>,
 }
 
 impl Default for DrawParam {
@@ -683,6 +1317,7 @@ impl Default for DrawParam {
             scale: Point::new(1.0, 1.0),
             offset: Point::new(0.0, 0.0),
             shear: Point::new(0.0, 0.0),
+            color: None,
         }
     }
 }
@@ -720,6 +1355,7 @@ pub struct ImageGeneric<R>
     where R: gfx::Resources
 {
     texture: gfx::handle::ShaderResourceView<R, [f32; 4]>,
+    raw_texture: gfx::handle::RawTexture<R>,
     sampler_info: gfx::texture::SamplerInfo,
     width: u32,
     height: u32,
@@ -729,6 +1365,13 @@ pub struct ImageGeneric<R>
 /// using the OpenGL backend.
 pub type Image = ImageGeneric<gfx_device_gl::Resources>;
 
+/// Returns the number of mipmap levels a texture of the given size
+/// has, going down by halves until it's a single pixel.
+fn mip_levels(width: u16, height: u16) -> gfx::texture::Level {
+    let largest = width.max(height).max(1);
+    (16 - largest.leading_zeros()) as gfx::texture::Level
+}
+
 /// Copies an 2D (RGBA) buffer into one that is the next
 /// power of two size up in both dimensions.  All data is
 /// retained and kept closest to [0,0]; anything extra is
@@ -774,6 +1417,23 @@ impl Image {
         Image::from_rgba8(context, width as u16, height as u16, &img)
     }
 
+    /// Load a new image from the file at the given path, with mipmaps:
+    /// smaller copies of it, which are used when drawing it scaled down
+    /// with `FilterMode::Trilinear` or `FilterMode::Anisotropic` to keep
+    /// it from shimmering.
+    pub fn new_mipmapped<P: AsRef<path::Path>>(context: &mut Context,
+                                               path: P)
+                                               -> GameResult<Image> {
+        let img = {
+            let mut buf = Vec::new();
+            let mut reader = context.filesystem.open(path)?;
+            reader.read_to_end(&mut buf)?;
+            image::load_from_memory(&buf)?.to_rgba()
+        };
+        let (width, height) = img.dimensions();
+        Image::from_rgba8_mipmapped(context, width as u16, height as u16, &img)
+    }
+
     /// Creates a new `Image` from the given buffer of `u8` RGBA values.
     pub fn from_rgba8(context: &mut Context,
                       width: u16,
@@ -786,6 +1446,43 @@ impl Image {
                         height,
                         rgba)
     }
+
+    /// Creates a new `Image` with mipmaps from the given buffer of `u8`
+    /// RGBA values.  See `new_mipmapped()`.
+    pub fn from_rgba8_mipmapped(context: &mut Context,
+                                width: u16,
+                                height: u16,
+                                rgba: &[u8])
+                                -> GameResult<Image> {
+        use gfx::format::Formatted;
+
+        check_texture_data(width, height, rgba)?;
+        let gfx = &mut context.gfx_context;
+        let kind = gfx::texture::Kind::D2(width, height, gfx::texture::AaMode::Single);
+        let levels = mip_levels(width, height);
+        let (raw_texture, view) = Image::create_texture(&mut gfx.factory, kind, levels, None)?;
+        let info = gfx::texture::RawImageInfo {
+            xoffset: 0,
+            yoffset: 0,
+            zoffset: 0,
+            width: width,
+            height: height,
+            depth: 0,
+            format: ColorFormat::get_format(),
+            mipmap: 0,
+        };
+        gfx.encoder
+            .update_texture_raw(&raw_texture, None, info, rgba)?;
+        gfx.encoder.generate_mipmap(&view);
+        Ok(Image {
+               texture: view,
+               raw_texture: raw_texture,
+               sampler_info: gfx.default_sampler_info,
+               width: width as u32,
+               height: height as u32,
+           })
+    }
+
     /// A helper function that just takes a factory directly so we can make an image
     /// without needing the full context object, so we can create an Image while still
     /// creating the GraphicsContext.
@@ -796,7 +1493,7 @@ impl Image {
                 rgba: &[u8])
                 -> GameResult<Image> {
         // Check if the texture is not power of 2, and if not, pad it out.
-        let view = if false {
+        let (raw_texture, view) = if false {
             // let view = if !(width.is_power_of_two() && height.is_power_of_two()) {
             let (width, height, rgba) = scale_rgba_up_to_power_of_2(width, height, rgba);
             let rgba = &rgba;
@@ -804,28 +1501,54 @@ impl Image {
             let kind = gfx::texture::Kind::D2(width, height, gfx::texture::AaMode::Single);
             // The slice containing rgba is NOT rows x columns, it is a slice of
             // MIPMAP LEVELS.  Augh!
-            let (_, view) = factory
-                .create_texture_immutable_u8::<gfx::format::Srgba8>(kind, &[rgba])?;
-            view
+            Image::create_texture(factory, kind, 1, Some(rgba))?
         } else {
-            if width == 0 || height == 0 {
-                let msg = format!("Tried to create a texture of size {}x{}, each dimension must be >0", width, height);
-                return Err(GameError::ResourceLoadError(msg));
-            }
+            check_texture_data(width, height, rgba)?;
             let kind = gfx::texture::Kind::D2(width, height, gfx::texture::AaMode::Single);
-            let (_, view) = factory
-                .create_texture_immutable_u8::<gfx::format::Srgba8>(kind, &[rgba])?;
-            view
-
+            Image::create_texture(factory, kind, 1, Some(rgba))?
         };
         Ok(Image {
                texture: view,
+               raw_texture: raw_texture,
                sampler_info: *sampler_info,
                width: width as u32,
                height: height as u32,
            })
     }
 
+    /// Creates a texture with the given number of mipmap levels, holding
+    /// the given RGBA data if any, which unlike
+    /// `Factory::create_texture_immutable_u8()` can be updated and read
+    /// back afterwards.
+    fn create_texture(factory: &mut gfx_device_gl::Factory,
+                      kind: gfx::texture::Kind,
+                      levels: gfx::texture::Level,
+                      rgba: Option<&[u8]>)
+                      -> GameResult<(gfx::handle::RawTexture<gfx_device_gl::Resources>,
+                                     gfx::handle::ShaderResourceView<gfx_device_gl::Resources,
+                                                                     [f32; 4]>)> {
+        use gfx::format::{ChannelTyped, Formatted, SurfaceTyped};
+        let info = gfx::texture::Info {
+            kind: kind,
+            levels: levels,
+            format: <<ColorFormat as Formatted>::Surface as SurfaceTyped>::get_surface_type(),
+            bind: gfx::SHADER_RESOURCE | gfx::TRANSFER_SRC | gfx::TRANSFER_DST,
+            usage: gfx::memory::Usage::Data,
+        };
+        let channel = <<ColorFormat as Formatted>::Channel as ChannelTyped>::get_channel_type();
+        let raw = match rgba {
+            Some(rgba) => factory.create_texture_raw(info, Some(channel), Some(&[rgba]))?,
+            None => factory.create_texture_raw(info, Some(channel), None)?,
+        };
+        let tex: gfx::handle::Texture<_, <ColorFormat as Formatted>::Surface> =
+            Typed::new(raw.clone());
+        let view = factory
+            .view_texture_as_shader_resource::<ColorFormat>(&tex,
+                                                            (0, levels - 1),
+                                                            gfx::format::Swizzle::new())?;
+        Ok((raw, view))
+    }
+
     /// A little helper function that creates a new Image that is just
     /// a solid square of the given size and color.  Mainly useful for
     /// debugging.
@@ -890,9 +1613,10 @@ impl fmt::Debug for Image {
 }
 
 
-impl Drawable for Image {
-    fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
-        let gfx = &mut ctx.gfx_context;
+impl Image {
+    /// Fudges the given `DrawParam` so that drawing the unit quad with it
+    /// draws the `src` portion of this image at its size in pixels.
+    fn scale_draw_param(&self, param: DrawParam, screen_rect: &Rect) -> DrawParam {
         let src_width = param.src.w;
         let src_height = param.src.h;
         // We have to mess with the scale to make everything
@@ -901,7 +1625,7 @@ impl Drawable for Image {
         // are "upside down", because by default we present the
         // illusion that the screen is addressed in pixels.
         // BUGGO: Which I rather regret now.
-        let invert_y = if gfx.screen_rect.h < 0.0 { 1.0 } else { -1.0 };
+        let invert_y = if screen_rect.h < 0.0 { 1.0 } else { -1.0 };
         let real_scale = Point {
             x: src_width * param.scale.x * self.width as f32,
             y: src_height * param.scale.y * self.height as f32 * invert_y,
@@ -911,13 +1635,26 @@ impl Drawable for Image {
         // Not entirely sure why the inversion is necessary, but oh well.
         new_param.offset.x *= -1.0 * param.scale.x;
         new_param.offset.y *= param.scale.y;
-        gfx.update_rect_properties(new_param)?;
+        new_param
+    }
+
+    /// Draws the image with a `DrawParam` that has already been
+    /// through `scale_draw_param()`.
+    fn draw_scaled(&self, gfx: &mut GraphicsContext, param: DrawParam) -> GameResult<()> {
+        gfx.update_rect_properties(param)?;
         let sampler = gfx.samplers
             .get_or_insert(self.sampler_info, gfx.factory.as_mut());
         gfx.data.vbuf = gfx.quad_vertex_buffer.clone();
         gfx.data.tex = (self.texture.clone(), sampler);
-        gfx.encoder.draw(&gfx.quad_slice, &gfx.pso, &gfx.data);
-        Ok(())
+        gfx.draw(None)
+    }
+}
+
+impl Drawable for Image {
+    fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
+        let gfx = &mut ctx.gfx_context;
+        let new_param = self.scale_draw_param(param, &gfx.screen_rect);
+        self.draw_scaled(gfx, new_param)
     }
 }
 
@@ -925,44 +1662,56 @@ impl Drawable for Image {
 pub struct Mesh {
     buffer: gfx::handle::Buffer<gfx_device_gl::Resources, Vertex>,
     slice: gfx::Slice<gfx_device_gl::Resources>,
+    image: Option<Image>,
 }
 
 impl Mesh {
-    fn from_tessellation(ctx: &mut Context, buffer: tessellation::Buffer) -> GameResult<Mesh> {
-        let (vbuf, slice) =
-            ctx.gfx_context
-                .factory
-                .create_vertex_buffer_with_slice(&buffer.vertices[..], &buffer.indices[..]);
+    fn from_tessellation(ctx: &mut Context, buffer: &tessellation::Buffer) -> GameResult<Mesh> {
+        let mut vertices = buffer.vertices.clone();
+        tessellation::normalize_uvs(&mut vertices);
+        let (vbuf, slice) = ctx.gfx_context
+            .factory
+            .create_vertex_buffer_with_slice(&vertices[..], &buffer.indices[..]);
 
         Ok(Mesh {
                buffer: vbuf,
                slice: slice,
+               image: None,
            })
     }
 
     /// Create a new mesh for a line of one or more connected segments.
-    pub fn new_line(ctx: &mut Context, points: &[Point], width: f32) -> GameResult<Mesh> {
-        Mesh::from_tessellation(ctx, tessellation::build_line(points, width)?)
+    /// Takes either a width or full `StrokeOptions`.
+    pub fn new_line<S>(ctx: &mut Context, points: &[Point], stroke: S) -> GameResult<Mesh>
+        where S: Into<StrokeOptions>
+    {
+        let tolerance = ctx.gfx_context.tolerance;
+        let buf = tessellation::build_line(points, &stroke.into(), tolerance)?;
+        Mesh::from_tessellation(ctx, &buf)
+    }
+
+    /// Create a new mesh for a line whose width changes along it,
+    /// with one width for each point.
+    pub fn new_variable_line(ctx: &mut Context,
+                             points: &[Point],
+                             widths: &[f32])
+                             -> GameResult<Mesh> {
+        Mesh::from_tessellation(ctx, &tessellation::build_variable_line(points, widths)?)
     }
 
     /// Create a new mesh for a circle.
-    /// Stroked circles are still WIP, sorry.
+    /// Stroked circles use the current `StrokeOptions`.
     pub fn new_circle(ctx: &mut Context,
                       mode: DrawMode,
                       point: Point,
                       radius: f32,
                       segments: u32)
                       -> GameResult<Mesh> {
-        let buf = match mode {
-            DrawMode::Fill => tessellation::build_ellipse_fill(point, radius, radius, segments),
-            DrawMode::Line => unimplemented!(),
-        }?;
-
-        Mesh::from_tessellation(ctx, buf)
+        Mesh::new_ellipse(ctx, mode, point, radius, radius, segments)
     }
 
     /// Create a new mesh for an ellipse.
-    /// Stroked ellipses are still WIP, sorry.
+    /// Stroked ellipses use the current `StrokeOptions`.
     pub fn new_ellipse(ctx: &mut Context,
                        mode: DrawMode,
                        point: Point,
@@ -970,26 +1719,124 @@ impl Mesh {
                        radius2: f32,
                        segments: u32)
                        -> GameResult<Mesh> {
+        let tolerance = ctx.gfx_context.tolerance;
+        let options = ctx.gfx_context.stroke_options.clone();
         let buf = match mode {
             DrawMode::Fill => tessellation::build_ellipse_fill(point, radius1, radius2, segments),
-            DrawMode::Line => unimplemented!(),
+            DrawMode::Line => {
+                tessellation::build_ellipse(point, radius1, radius2, segments, &options, tolerance)
+            }
+        }?;
+
+        Mesh::from_tessellation(ctx, &buf)
+    }
+
+    /// Create a new mesh for an arc of a circle, from `angle1` to `angle2`
+    /// in radians.  Stroked arcs use the current `StrokeOptions`.
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+    pub fn new_arc(ctx: &mut Context,
+                   mode: DrawMode,
+                   arc_mode: ArcMode,
+                   point: Point,
+                   radius: f32,
+                   angle1: f32,
+                   angle2: f32,
+                   segments: u32)
+                   -> GameResult<Mesh> {
+        let tolerance = ctx.gfx_context.tolerance;
+        let options = ctx.gfx_context.stroke_options.clone();
+        let mut points = tessellation::arc_points(point, radius, radius, angle1, angle2, segments);
+        if arc_mode == ArcMode::Pie {
+            points.insert(0, point);
+        }
+        let buf = match (mode, arc_mode) {
+            (DrawMode::Fill, _) => tessellation::build_polygon_fill(&points, tolerance),
+            (DrawMode::Line, ArcMode::Open) => {
+                tessellation::build_line(&points, &options, tolerance)
+            }
+            (DrawMode::Line, _) => tessellation::build_polygon(&points, &options, tolerance),
         }?;
 
-        Mesh::from_tessellation(ctx, buf)
+        Mesh::from_tessellation(ctx, &buf)
+    }
+
+    /// Create a new mesh for a rectangle with rounded corners of the given
+    /// radius.  Like `rectangle()`, the rect is centered on its `x` and `y`.
+    /// Stroked rectangles use the current `StrokeOptions`.
+    pub fn new_rounded_rectangle(ctx: &mut Context,
+                                 mode: DrawMode,
+                                 rect: Rect,
+                                 radius: f32)
+                                 -> GameResult<Mesh> {
+        let x1 = rect.x - (rect.w / 2.0);
+        let x2 = rect.x + (rect.w / 2.0);
+        let y1 = rect.y - (rect.h / 2.0);
+        let y2 = rect.y + (rect.h / 2.0);
+        let points =
+            tessellation::rounded_rectangle_points(x1, y1, x2, y2, radius, ctx.gfx_context.tolerance);
+        let options = ctx.gfx_context.stroke_options.clone();
+        Mesh::new_polygon(ctx, mode, &points, options)
+    }
+
+    /// Create a new mesh for a cubic bezier curve from `start` to `end`,
+    /// bending towards the two control points.
+    /// Takes either a width or full `StrokeOptions`.
+    pub fn new_bezier<S>(ctx: &mut Context,
+                         start: Point,
+                         control1: Point,
+                         control2: Point,
+                         end: Point,
+                         stroke: S)
+                         -> GameResult<Mesh>
+        where S: Into<StrokeOptions>
+    {
+        let tolerance = ctx.gfx_context.tolerance;
+        let buf = tessellation::build_bezier(start,
+                                             control1,
+                                             control2,
+                                             end,
+                                             &stroke.into(),
+                                             tolerance)?;
+        Mesh::from_tessellation(ctx, &buf)
+    }
+
+    /// Create a new mesh for a quadratic bezier curve from `start` to `end`,
+    /// bending towards the control point.
+    /// Takes either a width or full `StrokeOptions`.
+    pub fn new_quadratic_bezier<S>(ctx: &mut Context,
+                                   start: Point,
+                                   control: Point,
+                                   end: Point,
+                                   stroke: S)
+                                   -> GameResult<Mesh>
+        where S: Into<StrokeOptions>
+    {
+        let tolerance = ctx.gfx_context.tolerance;
+        let buf = tessellation::build_quadratic_bezier(start,
+                                                       control,
+                                                       end,
+                                                       &stroke.into(),
+                                                       tolerance)?;
+        Mesh::from_tessellation(ctx, &buf)
     }
 
     /// Create a new mesh for a closed polygon.
-    pub fn new_polygon(ctx: &mut Context,
-                       mode: DrawMode,
-                       points: &[Point],
-                       width: f32)
-                       -> GameResult<Mesh> {
+    /// Takes either a width or full `StrokeOptions`, which filled
+    /// polygons ignore.
+    pub fn new_polygon<S>(ctx: &mut Context,
+                          mode: DrawMode,
+                          points: &[Point],
+                          stroke: S)
+                          -> GameResult<Mesh>
+        where S: Into<StrokeOptions>
+    {
+        let tolerance = ctx.gfx_context.tolerance;
         let buf = match mode {
-            DrawMode::Fill => tessellation::build_polygon_fill(points),
-            DrawMode::Line => tessellation::build_polygon(points, width),
+            DrawMode::Fill => tessellation::build_polygon_fill(points, tolerance),
+            DrawMode::Line => tessellation::build_polygon(points, &stroke.into(), tolerance),
         }?;
 
-        Mesh::from_tessellation(ctx, buf)
+        Mesh::from_tessellation(ctx, &buf)
     }
 
     /// Create a new `Mesh` from a raw list of triangles.
@@ -1001,6 +1848,7 @@ impl Mesh {
             .map(|p| Vertex{
                 pos: (*p).into(),
                 uv: (*p).into(),
+                color: [1.0, 1.0, 1.0, 1.0],
             }).collect();
         let (vbuf, slice) =
             ctx.gfx_context
@@ -1010,9 +1858,210 @@ impl Mesh {
         Ok(Mesh {
                buffer: vbuf,
                slice: slice,
+               image: None,
+           })
+    }
+
+    /// Create a new `Mesh` from the given vertices, drawn as triangles
+    /// using each three indices into them in turn, and textured with the
+    /// given `Image`, if any.  Without one, the mesh is just colored by
+    /// its vertices.
+    pub fn from_raw(ctx: &mut Context,
+                    vertices: &[Vertex],
+                    indices: &[u16],
+                    image: Option<Image>)
+                    -> GameResult<Mesh> {
+        if indices.len() % 3 != 0 {
+            let msg = format!("Mesh indices should come in threes, got {}", indices.len());
+            return Err(GameError::RenderError(msg));
+        }
+        if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+            let msg = format!("Mesh index {} is out of range of its {} vertices",
+                              i,
+                              vertices.len());
+            return Err(GameError::RenderError(msg));
+        }
+        let (vbuf, slice) = ctx.gfx_context
+            .factory
+            .create_vertex_buffer_with_slice(vertices, indices);
+
+        Ok(Mesh {
+               buffer: vbuf,
+               slice: slice,
+               image: image,
            })
     }
 
+    /// Returns the `Image` the mesh is textured with, if any.
+    pub fn get_image(&self) -> Option<&Image> {
+        self.image.as_ref()
+    }
+
+    /// Sets the `Image` the mesh is textured with, or `None` to
+    /// color it by its vertices alone.  Meshes made from shapes have
+    /// the image stretched over their bounding box; meshes made with
+    /// `from_raw()` use their vertices' own texture coordinates.
+    pub fn set_image(&mut self, image: Option<Image>) {
+        self.image = image;
+    }
+}
+
+/// Builds a single `Mesh` out of many shapes, each with its own color
+/// or `Gradient`, so they can all be drawn at once.
+///
+/// ```rust,ignore
+/// let mesh = graphics::MeshBuilder::new()
+///     .line(&[Point::new(0.0, 0.0), Point::new(100.0, 100.0)], graphics::WHITE)?
+///     .circle(DrawMode::Fill, Point::new(50.0, 50.0), 10.0, 16, graphics::BLACK)?
+///     .build(ctx)?;
+/// ```
+pub struct MeshBuilder {
+    buffer: tessellation::Buffer,
+    stroke_options: StrokeOptions,
+    tolerance: f32,
+}
+
+impl Default for MeshBuilder {
+    fn default() -> Self {
+        MeshBuilder {
+            buffer: tessellation::Buffer::new(),
+            stroke_options: StrokeOptions::default(),
+            tolerance: tessellation::DEFAULT_TOLERANCE,
+        }
+    }
+}
+
+impl fmt::Debug for MeshBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "<MeshBuilder: {} vertices, {:p}>",
+               self.buffer.vertices.len(),
+               self)
+    }
+}
+
+impl MeshBuilder {
+    /// Creates a new, empty `MeshBuilder`.
+    pub fn new() -> Self {
+        MeshBuilder::default()
+    }
+
+    /// Sets the width of lines and stroked shapes added from now on.
+    pub fn set_line_width(&mut self, width: f32) -> &mut Self {
+        self.stroke_options.width = width;
+        self
+    }
+
+    /// Sets the `StrokeOptions` of lines and stroked shapes added from now on.
+    pub fn set_stroke_options(&mut self, options: StrokeOptions) -> &mut Self {
+        self.stroke_options = options;
+        self
+    }
+
+    /// Sets the tolerance used for curved shapes added from now on,
+    /// like `graphics::set_tolerance()`.
+    pub fn set_tolerance(&mut self, tolerance: f32) -> &mut Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    fn append<P>(&mut self, buffer: tessellation::Buffer, paint: P) -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        let paint = paint.into();
+        tessellation::append_buffer(&mut self.buffer, buffer, |pos| {
+            paint.color_at(Point::new(pos[0], pos[1])).into()
+        })?;
+        Ok(self)
+    }
+
+    /// Adds a line of one or more connected segments.
+    pub fn line<P>(&mut self, points: &[Point], paint: P) -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        let buffer = tessellation::build_line(points, &self.stroke_options, self.tolerance)?;
+        self.append(buffer, paint)
+    }
+
+    /// Adds a closed polygon.
+    pub fn polygon<P>(&mut self,
+                      mode: DrawMode,
+                      points: &[Point],
+                      paint: P)
+                      -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        let buffer = match mode {
+            DrawMode::Fill => tessellation::build_polygon_fill(points, self.tolerance),
+            DrawMode::Line => {
+                tessellation::build_polygon(points, &self.stroke_options, self.tolerance)
+            }
+        }?;
+        self.append(buffer, paint)
+    }
+
+    /// Adds a rectangle, centered on the rect's `x` and `y` like `graphics::rectangle()`.
+    pub fn rectangle<P>(&mut self, mode: DrawMode, rect: Rect, paint: P) -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        let x1 = rect.x - (rect.w / 2.0);
+        let x2 = rect.x + (rect.w / 2.0);
+        let y1 = rect.y - (rect.h / 2.0);
+        let y2 = rect.y + (rect.h / 2.0);
+        let points = [[x1, y1].into(), [x2, y1].into(), [x2, y2].into(), [x1, y2].into()];
+        self.polygon(mode, &points, paint)
+    }
+
+    /// Adds a circle.
+    pub fn circle<P>(&mut self,
+                     mode: DrawMode,
+                     point: Point,
+                     radius: f32,
+                     segments: u32,
+                     paint: P)
+                     -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        self.ellipse(mode, point, radius, radius, segments, paint)
+    }
+
+    /// Adds an ellipse.
+    pub fn ellipse<P>(&mut self,
+                      mode: DrawMode,
+                      point: Point,
+                      radius1: f32,
+                      radius2: f32,
+                      segments: u32,
+                      paint: P)
+                      -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        let buffer = match mode {
+            DrawMode::Fill => tessellation::build_ellipse_fill(point, radius1, radius2, segments),
+            DrawMode::Line => {
+                tessellation::build_ellipse(point,
+                                            radius1,
+                                            radius2,
+                                            segments,
+                                            &self.stroke_options,
+                                            self.tolerance)
+            }
+        }?;
+        self.append(buffer, paint)
+    }
+
+    /// Adds a raw list of triangles, three points each.
+    pub fn triangles<P>(&mut self, triangles: &[Point], paint: P) -> GameResult<&mut Self>
+        where P: Into<Paint>
+    {
+        let buffer = tessellation::build_triangles(triangles)?;
+        self.append(buffer, paint)
+    }
+
+    /// Creates a `Mesh` out of everything added so far.
+    pub fn build(&self, ctx: &mut Context) -> GameResult<Mesh> {
+        Mesh::from_tessellation(ctx, &self.buffer)
+    }
 }
 
 impl Drawable for Mesh {
@@ -1021,17 +2070,101 @@ impl Drawable for Mesh {
         gfx.update_rect_properties(param)?;
 
         gfx.data.vbuf = self.buffer.clone();
-        gfx.data.tex.0 = gfx.white_image.texture.clone();
-
-        gfx.encoder.draw(&self.slice, &gfx.pso, &gfx.data);
+        let image = self.image.as_ref().unwrap_or(&gfx.white_image);
+        let sampler = gfx.samplers
+            .get_or_insert(image.sampler_info, gfx.factory.as_mut());
+        gfx.data.tex = (image.texture.clone(), sampler);
 
-        Ok(())
+        gfx.draw(Some(&self.slice))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn test_free_dropped_slots() {
+        let (a, b) = (Rc::new(()), Rc::new(()));
+        // A built-in shader with no owner, then two user-defined ones.
+        let mut slots = vec![Some(None), Some(Some(Rc::downgrade(&a))),
+                             Some(Some(Rc::downgrade(&b)))];
+        // Like a `ShaderLock` that replaced `a` and may go back to it.
+        let lock = Rc::downgrade(&a).upgrade();
+        drop(a);
+        drop(b);
+        free_dropped_slots(&mut slots, |owner: &Option<Weak<()>>| owner.as_ref());
+        assert!(slots[0].is_some());
+        assert!(slots[1].is_some());
+        assert!(slots[2].is_none());
+        // So a new shader can't take over the slot the lock goes back to.
+        assert_eq!(slots.iter().position(|slot| slot.is_none()), Some(2));
+
+        drop(lock);
+        free_dropped_slots(&mut slots, |owner: &Option<Weak<()>>| owner.as_ref());
+        assert!(slots[1].is_none());
+    }
+
+    #[test]
+    fn test_draw_param_matrix() {
+        let identity = [[1.0, 0.0, 0.0, 0.0],
+                        [0.0, 1.0, 0.0, 0.0],
+                        [0.0, 0.0, 1.0, 0.0],
+                        [0.0, 0.0, 0.0, 1.0]];
+        assert_eq!(draw_param_matrix(&DrawParam::default()), identity);
+
+        let param = DrawParam {
+            dest: Point::new(10.0, 20.0),
+            scale: Point::new(2.0, 3.0),
+            ..Default::default()
+        };
+        let m = draw_param_matrix(&param);
+        assert_eq!(m,
+                   [[2.0, 0.0, 0.0, 10.0],
+                    [0.0, 3.0, 0.0, 20.0],
+                    [0.0, 0.0, 1.0, 0.0],
+                    [0.0, 0.0, 0.0, 1.0]]);
+        assert_eq!(matrix_mul(&identity, &m), m);
+        assert_eq!(matrix_mul(&m, &identity), m);
+    }
+
+    #[test]
+    fn test_mip_levels() {
+        assert_eq!(mip_levels(1, 1), 1);
+        assert_eq!(mip_levels(2, 1), 2);
+        assert_eq!(mip_levels(256, 100), 9);
+        assert_eq!(mip_levels(300, 1), 9);
+    }
+
+    #[test]
+    fn test_check_texture_data() {
+        assert!(check_texture_data(2, 1, &[0; 8]).is_ok());
+        assert!(check_texture_data(2, 1, &[0; 4]).is_err());
+        assert!(check_texture_data(0, 1, &[]).is_err());
+    }
+
+    #[test]
+    fn test_scissor_pixels() {
+        // The default coordinate system for an 800x600 window.
+        let screen_rect = Rect::new(0.0, 600.0, 800.0, -600.0);
+        let rect = Rect::new(100.0, 50.0, 200.0, 150.0);
+        assert_eq!(scissor_pixels(rect, screen_rect, 800, 600),
+                   gfx::Rect {
+                       x: 100,
+                       y: 400,
+                       w: 200,
+                       h: 150,
+                   });
+
+        let offscreen = Rect::new(-100.0, 500.0, 200.0, 200.0);
+        assert_eq!(scissor_pixels(offscreen, screen_rect, 800, 600),
+                   gfx::Rect {
+                       x: 0,
+                       y: 0,
+                       w: 100,
+                       h: 100,
+                   });
+    }
+
     #[test]
     fn test_image_scaling_up() {
         let mut from: Vec<u8> = Vec::new();
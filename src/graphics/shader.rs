@@ -0,0 +1,213 @@
+//! The `shader` module allows user-defined shaders to be used
+//! with ggez for cool and spooky effects.
+//!
+//! A `Shader` is a GLSL vertex and pixel (fragment) shader pair, plus a
+//! block of constants that can be sent to it.  While it is in use, every
+//! `Drawable` is drawn through it instead of through ggez's built-in shader.
+//!
+//! A custom shader gets the same inputs as the built-in one, which can be
+//! found in `src/graphics/shader/basic_150.glslv` and `basic_150.glslf`.
+//! That means it has to declare the `Globals` uniform block the same way
+//! those do, and a vertex shader has to take the same vertex attributes;
+//! the easiest way to get that right is to use the built-in vertex shader
+//! itself.  Its own constants go in a uniform block of your choice,
+//! described on the Rust side by a `constant` struct declared with
+//! `gfx_defines!`:
+//!
+//! ```rust,ignore
+//! gfx_defines!{
+//!     constant Dim {
+//!         rate: f32 = "u_Rate",
+//!     }
+//! }
+//!
+//! let dim = Dim { rate: 0.5 };
+//! let shader = graphics::Shader::from_u8(ctx,
+//!                                        include_bytes!("basic_150.glslv"),
+//!                                        include_bytes!("dimmer_150.glslf"),
+//!                                        dim,
+//!                                        "Dim")?;
+//! {
+//!     let _lock = graphics::use_shader(ctx, &shader);
+//!     // Everything drawn here goes through the shader.
+//! }
+//! ```
+//!
+//! Each shader gets exactly one block of its own constants.  ggez's
+//! pipeline is declared ahead of time with `gfx_defines!`, which fixes
+//! how many constant buffers it binds, so a shader that needs several
+//! groups of values should put them all in the one block.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io::Read;
+use std::path;
+use std::rc::Rc;
+
+use gfx;
+use gfx::memory::Typed;
+use gfx::traits::{FactoryExt, Pod};
+
+use super::*;
+
+/// An identifier for a shader program stored in the `GraphicsContext`.
+pub type ShaderId = usize;
+
+/// The `ShaderId` of ggez's built-in shader.
+pub const DEFAULT_SHADER: ShaderId = 0;
+
+/// A user-defined shader, with constants of type `C`.
+///
+/// Once a `Shader` is dropped, its program is freed the next time
+/// the frame is presented or another shader is created, unless a
+/// `ShaderLock` will still go back to it.
+pub struct Shader<C>
+    where C: Pod
+{
+    id: ShaderId,
+    buffer: gfx::handle::Buffer<gfx_device_gl::Resources, C>,
+    // The program holds a weak reference to this, to tell when
+    // the `Shader` is gone.
+    #[allow(dead_code)]
+    handle: Rc<()>,
+}
+
+impl<C> Shader<C>
+    where C: Pod
+{
+    /// Loads a new shader from the GLSL vertex and pixel shader files at
+    /// the given paths.  `consts` is the initial value of the shader's
+    /// constants, and `name` is the name of the uniform block they go in.
+    pub fn new<P: AsRef<path::Path>>(ctx: &mut Context,
+                                     vertex_path: P,
+                                     pixel_path: P,
+                                     consts: C,
+                                     name: &str)
+                                     -> GameResult<Shader<C>> {
+        let vertex_source = {
+            let mut buf = Vec::new();
+            let mut reader = ctx.filesystem.open(vertex_path)?;
+            reader.read_to_end(&mut buf)?;
+            buf
+        };
+        let pixel_source = {
+            let mut buf = Vec::new();
+            let mut reader = ctx.filesystem.open(pixel_path)?;
+            reader.read_to_end(&mut buf)?;
+            buf
+        };
+        Shader::from_u8(ctx, &vertex_source, &pixel_source, consts, name)
+    }
+
+    /// Creates a new shader from the given GLSL vertex and pixel shader source.
+    pub fn from_u8(ctx: &mut Context,
+                   vertex_source: &[u8],
+                   pixel_source: &[u8],
+                   consts: C,
+                   name: &str)
+                   -> GameResult<Shader<C>> {
+        let gfx = &mut ctx.gfx_context;
+        let buffer = gfx.factory.create_constant_buffer(1);
+        let mut program = ShaderProgram::new(gfx.factory.as_mut(),
+                                             vertex_source,
+                                             pixel_source,
+                                             name,
+                                             buffer.raw().clone())?;
+        gfx.encoder.update_buffer(&buffer, &[consts], 0)?;
+
+        let handle = Rc::new(());
+        program.owner = Some(Rc::downgrade(&handle));
+        gfx.free_dropped_shaders();
+        let id = match gfx.shaders.iter().position(|slot| slot.is_none()) {
+            Some(id) => {
+                gfx.shaders[id] = Some(program);
+                id
+            }
+            None => {
+                gfx.shaders.push(Some(program));
+                gfx.shaders.len() - 1
+            }
+        };
+        Ok(Shader {
+               id: id,
+               buffer: buffer,
+               handle: handle,
+           })
+    }
+
+    /// Sends new values for the shader's constants.
+    pub fn send(&self, ctx: &mut Context, consts: C) -> GameResult<()> {
+        ctx.gfx_context
+            .encoder
+            .update_buffer(&self.buffer, &[consts], 0)?;
+        Ok(())
+    }
+
+    /// Returns the `ShaderId` of the shader.
+    pub fn shader_id(&self) -> ShaderId {
+        self.id
+    }
+}
+
+impl<C> fmt::Debug for Shader<C>
+    where C: Pod
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Shader: {}, {:p}>", self.id, self)
+    }
+}
+
+/// Keeps a shader in use until it is dropped, then goes
+/// back to whatever shader was in use before.
+///
+/// Returned by `use_shader()`.
+#[derive(Debug)]
+pub struct ShaderLock {
+    cell: Rc<Cell<ShaderId>>,
+    previous_shader: ShaderId,
+    // Keeps the previous shader's program, if it's a user-defined one,
+    // from being freed and its slot given to another shader while this
+    // lock may still go back to it.
+    #[allow(dead_code)]
+    previous_owner: Option<Rc<()>>,
+}
+
+impl Drop for ShaderLock {
+    fn drop(&mut self) {
+        self.cell.set(self.previous_shader);
+    }
+}
+
+/// Uses the given shader for all drawing until the returned
+/// `ShaderLock` goes out of scope.
+pub fn use_shader<C>(ctx: &mut Context, shader: &Shader<C>) -> ShaderLock
+    where C: Pod
+{
+    let gfx = &ctx.gfx_context;
+    let cell = gfx.current_shader.clone();
+    let previous_shader = cell.get();
+    let previous_owner = gfx.shaders
+        .get(previous_shader)
+        .and_then(|slot| slot.as_ref())
+        .and_then(|program| program.owner.as_ref())
+        .and_then(|owner| owner.upgrade());
+    cell.set(shader.id);
+    ShaderLock {
+        cell: cell,
+        previous_shader: previous_shader,
+        previous_owner: previous_owner,
+    }
+}
+
+/// Uses the given shader for all drawing from now on,
+/// until `clear_shader()` or `set_shader()` is called.
+pub fn set_shader<C>(ctx: &mut Context, shader: &Shader<C>)
+    where C: Pod
+{
+    ctx.gfx_context.current_shader.set(shader.id);
+}
+
+/// Goes back to drawing with ggez's built-in shader.
+pub fn clear_shader(ctx: &mut Context) {
+    ctx.gfx_context.current_shader.set(DEFAULT_SHADER);
+}
@@ -0,0 +1,146 @@
+//! A `SpriteBatch` is a way to efficiently draw a large
+//! number of copies of the same image, or parts of the same image.
+//! It's useful for particle systems, tilemaps and the like.
+//!
+//! Each sprite in the batch is described by its own `DrawParam`,
+//! and the whole batch is drawn with a single instanced draw call.
+
+use gfx;
+use gfx::Factory;
+
+use super::*;
+
+/// A handle referring to a sprite added to a `SpriteBatch`.
+pub type SpriteIdx = usize;
+
+/// A set of sprites that all use the same `Image` and get drawn
+/// at once.
+#[derive(Debug, Clone)]
+pub struct SpriteBatch {
+    image: Image,
+    sprites: Vec<DrawParam>,
+}
+
+impl SpriteBatch {
+    /// Creates a new, empty `SpriteBatch` drawing the given `Image`.
+    pub fn new(image: Image) -> Self {
+        SpriteBatch {
+            image: image,
+            sprites: Vec::new(),
+        }
+    }
+
+    /// Adds a new sprite to the batch, returning a handle that
+    /// can be used to change it later.
+    pub fn add(&mut self, param: DrawParam) -> SpriteIdx {
+        self.sprites.push(param);
+        self.sprites.len() - 1
+    }
+
+    /// Alters the sprite referred to by the given handle.
+    pub fn set(&mut self, handle: SpriteIdx, param: DrawParam) -> GameResult<()> {
+        match self.sprites.get_mut(handle) {
+            Some(sprite) => {
+                *sprite = param;
+                Ok(())
+            }
+            None => {
+                let msg = format!("Sprite handle {} is not in the SpriteBatch", handle);
+                Err(GameError::RenderError(msg))
+            }
+        }
+    }
+
+    /// Gets the `DrawParam` of the sprite referred to by the given handle,
+    /// if it exists.
+    pub fn get(&self, handle: SpriteIdx) -> Option<DrawParam> {
+        self.sprites.get(handle).cloned()
+    }
+
+    /// Removes all sprites from the batch.
+    /// Any handles previously returned by `add()` are no longer valid.
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+
+    /// Returns the number of sprites in the batch.
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    /// Returns true if there are no sprites in the batch.
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// Returns the `Image` the batch draws.
+    pub fn get_image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Sets the `Image` the batch draws, returning the old one.
+    pub fn set_image(&mut self, image: Image) -> Image {
+        ::std::mem::replace(&mut self.image, image)
+    }
+
+    /// Uploads the properties of all the sprites into the instance
+    /// buffer, growing it if necessary.  Each sprite's color is
+    /// multiplied by `color`, if there is one.
+    fn flush(&self, ctx: &mut Context, color: Option<Color>) -> GameResult<()> {
+        let gfx = &mut ctx.gfx_context;
+        if gfx.data.rect_instance_properties.len() < self.sprites.len() {
+            gfx.data.rect_instance_properties =
+                gfx.factory
+                    .create_buffer(self.sprites.len(),
+                                   gfx::buffer::Role::Vertex,
+                                   gfx::memory::Usage::Dynamic,
+                                   gfx::TRANSFER_DST)?;
+        }
+        let properties = self.sprites
+            .iter()
+            .map(|param| {
+                     let new_param = self.image.scale_draw_param(*param, &gfx.screen_rect);
+                     let mut properties = gfx.rect_properties(new_param);
+                     if let Some(color) = color {
+                         let color: [f32; 4] = color.into();
+                         for (c, tint) in properties.color.iter_mut().zip(&color) {
+                             *c *= *tint;
+                         }
+                     }
+                     properties
+                 })
+            .collect::<Vec<RectProperties>>();
+        gfx.encoder
+            .update_buffer(&gfx.data.rect_instance_properties, &properties[..], 0)?;
+        Ok(())
+    }
+}
+
+impl Drawable for SpriteBatch {
+    /// Draws all the sprites in the batch.  The `DrawParam`
+    /// is applied to the batch as a whole, on top of each sprite's
+    /// own `DrawParam`; its `color`, if any, tints every sprite, and
+    /// its `src` field is ignored.
+    fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
+        if self.sprites.is_empty() {
+            return Ok(());
+        }
+        self.flush(ctx, param.color)?;
+        let gfx = &mut ctx.gfx_context;
+        let sampler = gfx.samplers
+            .get_or_insert(self.image.sampler_info, gfx.factory.as_mut());
+        gfx.data.vbuf = gfx.quad_vertex_buffer.clone();
+        gfx.data.tex = (self.image.texture.clone(), sampler);
+        let mut slice = gfx.quad_slice.clone();
+        slice.instances = Some((self.sprites.len() as u32, 0));
+
+        let projection = gfx.shader_globals.transform;
+        gfx.shader_globals.transform = matrix_mul(&projection, &draw_param_matrix(&param));
+        // Put the transform back even if drawing fails, or everything
+        // drawn after would be moved by this batch's transform too.
+        let result = gfx.update_globals().and_then(|_| gfx.draw(Some(&slice)));
+        gfx.shader_globals.transform = projection;
+        let restored = gfx.update_globals();
+        result.and(restored)
+    }
+}
@@ -1,5 +1,8 @@
+use std::f32::consts::PI;
+use std::u16;
+
 use lyon::path;
-use lyon::path_builder::BaseBuilder;
+use lyon::path_builder::{BaseBuilder, PathBuilder};
 use lyon::path_iterator::PathIterator;
 use lyon::tessellation;
 use lyon::tessellation::basic_shapes;
@@ -8,13 +11,15 @@ use lyon::tessellation::path_stroke;
 use lyon::tessellation::path_fill;
 use lyon::tessellation::geometry_builder;
 
-use super::{Point, Vertex};
+use super::{LineCap, LineJoin, Point, StrokeOptions, Vertex};
 use GameError;
 use GameResult;
 
 pub type Buffer = geometry_builder::VertexBuffers<Vertex>;
 
-const FLATTEN_TOLERANCE: f32 = 0.5;
+/// The default for how far, in pixels, curves may be off from the
+/// straight line segments they're approximated with.
+pub const DEFAULT_TOLERANCE: f32 = 0.5;
 
 pub struct VertexConstructor {
     stroke_width: f32,
@@ -26,6 +31,7 @@ impl geometry_builder::VertexConstructor<tessellation::StrokeVertex, Vertex> for
         Vertex {
             pos: [c.x, c.y],
             uv: [c.x, c.y],
+            color: [1.0, 1.0, 1.0, 1.0],
         }
     }
 }
@@ -36,6 +42,7 @@ impl geometry_builder::VertexConstructor<tessellation::FillVertex, Vertex> for V
         Vertex {
             pos: [p.x, p.y],
             uv: [p.x, p.y],
+            color: [1.0, 1.0, 1.0, 1.0],
         }
     }
 }
@@ -72,32 +79,318 @@ fn build_geometry<F, V, E>(line_width: f32, f: F) -> GameResult<Buffer>
     Ok(buffers)
 }
 
-fn build_stroke(points: &[Point], close: bool, line_width: f32) -> GameResult<Buffer> {
-    let path = build_path(points, close);
-    let path_iter = path.path_iter().flattened(FLATTEN_TOLERANCE);
-    let opts = path_stroke::StrokeOptions::default();
+fn lyon_stroke_options(options: &StrokeOptions, tolerance: f32) -> path_stroke::StrokeOptions {
+    let join = match options.join {
+        LineJoin::Miter => tessellation::LineJoin::Miter,
+        LineJoin::Round => tessellation::LineJoin::Round,
+        LineJoin::Bevel => tessellation::LineJoin::Bevel,
+    };
+    let cap = match options.cap {
+        LineCap::Butt => tessellation::LineCap::Butt,
+        LineCap::Round => tessellation::LineCap::Round,
+        LineCap::Square => tessellation::LineCap::Square,
+    };
+    path_stroke::StrokeOptions::default()
+        .with_line_join(join)
+        .with_line_cap(cap)
+        .with_miter_limit(options.miter_limit)
+        .with_tolerance(tolerance)
+}
+
+fn stroke_path(path: path::Path, options: &StrokeOptions, tolerance: f32) -> GameResult<Buffer> {
+    let path_iter = path.path_iter().flattened(tolerance);
+    let opts = lyon_stroke_options(options, tolerance);
     let mut tessellator = path_stroke::StrokeTessellator::new();
-    build_geometry(line_width, |builder| tessellator.tessellate(path_iter, &opts, builder))
+    build_geometry(options.width,
+                   |builder| tessellator.tessellate(path_iter, &opts, builder))
 }
 
-pub fn build_line(points: &[Point], line_width: f32) -> GameResult<Buffer> {
-    build_stroke(points, false, line_width)
+fn build_stroke(points: &[Point],
+                close: bool,
+                options: &StrokeOptions,
+                tolerance: f32)
+                -> GameResult<Buffer> {
+    if options.dashes.is_empty() {
+        return stroke_path(build_path(points, close), options, tolerance);
+    }
+    let white = [1.0, 1.0, 1.0, 1.0];
+    let mut buffer = Buffer::new();
+    let dots = options.cap != LineCap::Butt;
+    for dash in dash_points(points, close, &options.dashes, dots) {
+        let dash_buffer = stroke_path(build_path(&dash, false), options, tolerance)?;
+        append_buffer(&mut buffer, dash_buffer, |_| white)?;
+    }
+    Ok(buffer)
+}
+
+/// How long a piece of line a zero-length dash turns into, so that
+/// a round or square cap has a direction to draw a dot in.
+const DOT_LENGTH: f32 = 0.01;
+
+/// Splits a line into the pieces that are drawn with the given pattern
+/// of dash and gap lengths, starting with a dash.  If the pattern can't
+/// be walked along, for instance because it is all zeroes, the whole
+/// line is returned as one piece.
+///
+/// Zero-length dashes have nothing to draw with a butt cap, but round
+/// and square caps draw them as dots; if `dots` is true they are kept
+/// as a tiny piece of line pointing the way the line goes.
+pub fn dash_points(points: &[Point],
+                   closed: bool,
+                   dashes: &[f32],
+                   dots: bool)
+                   -> Vec<Vec<Point>> {
+    let mut line = points.to_vec();
+    if closed && !points.is_empty() {
+        line.push(points[0]);
+    }
+    let total: f32 = dashes.iter().sum();
+    if line.len() < 2 || total <= 0.0 || dashes.iter().any(|&d| d < 0.0) {
+        return vec![line];
+    }
+
+    // Dashes ending right on a corner pick it up twice, which is
+    // tidied up here along with the dots.
+    let mut pieces = Vec::new();
+    {
+        let mut finish = |mut piece: Vec<Point>, (dx, dy): (f32, f32)| {
+            piece.dedup();
+            if piece.len() == 1 && dots {
+                let p = piece[0];
+                piece.push(Point::new(p.x + dx * DOT_LENGTH, p.y + dy * DOT_LENGTH));
+            }
+            if piece.len() > 1 {
+                pieces.push(piece);
+            }
+        };
+        let mut current = vec![line[0]];
+        let mut direction = (0.0, 0.0);
+        let mut dash = 0;
+        let mut remaining = dashes[0];
+        let mut on = true;
+        for pair in line.windows(2) {
+            let (mut start, end) = (pair[0], pair[1]);
+            let (dx, dy) = (end.x - start.x, end.y - start.y);
+            let mut length = (dx * dx + dy * dy).sqrt();
+            if length == 0.0 {
+                continue;
+            }
+            direction = (dx / length, dy / length);
+            while length > remaining {
+                let t = remaining / length;
+                let split = Point::new(start.x + (end.x - start.x) * t,
+                                       start.y + (end.y - start.y) * t);
+                if on {
+                    current.push(split);
+                    finish(::std::mem::replace(&mut current, Vec::new()), direction);
+                } else {
+                    current = vec![split];
+                }
+                on = !on;
+                dash = (dash + 1) % dashes.len();
+                length -= remaining;
+                remaining = dashes[dash];
+                start = split;
+            }
+            remaining -= length;
+            if on {
+                current.push(end);
+            }
+        }
+        if on {
+            finish(current, direction);
+        }
+    }
+    pieces
+}
+
+pub fn build_line(points: &[Point], options: &StrokeOptions, tolerance: f32) -> GameResult<Buffer> {
+    build_stroke(points, false, options, tolerance)
 }
 
 /// Build a closed polygon.  Identical to `build_line` but closes the path,
 /// which makes sure the two endpoints actually line up.
-pub fn build_polygon(points: &[Point], line_width: f32) -> GameResult<Buffer> {
-    build_stroke(points, true, line_width)
+pub fn build_polygon(points: &[Point],
+                     options: &StrokeOptions,
+                     tolerance: f32)
+                     -> GameResult<Buffer> {
+    build_stroke(points, true, options, tolerance)
 }
 
-pub fn build_polygon_fill(points: &[Point]) -> GameResult<Buffer> {
+/// Build a line whose width changes from point to point, with one width
+/// per point.  Each width is the full width of the line at that point,
+/// so it reaches half of it out to either side.  Corners are always mitered, and the ends are butt capped.
+pub fn build_variable_line(points: &[Point], widths: &[f32]) -> GameResult<Buffer> {
+    if points.len() != widths.len() {
+        let msg = format!("A variable width line needs one width per point; got {} points \
+                           and {} widths",
+                          points.len(),
+                          widths.len());
+        return Err(GameError::RenderError(msg));
+    }
+    if points.len() > u16::MAX as usize / 2 {
+        let msg = String::from("Too many vertices for one mesh; they have to fit in a u16");
+        return Err(GameError::RenderError(msg));
+    }
+    let normal = |a: Point, b: Point| {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            (0.0, 0.0)
+        } else {
+            (-dy / length, dx / length)
+        }
+    };
+
+    let mut buffer = Buffer::new();
+    let last = points.len().saturating_sub(1);
+    for (i, (&p, &width)) in points.iter().zip(widths).enumerate() {
+        let before = if i > 0 { normal(points[i - 1], p) } else { (0.0, 0.0) };
+        let after = if i < last { normal(p, points[i + 1]) } else { (0.0, 0.0) };
+        let (nx, ny) = (before.0 + after.0, before.1 + after.1);
+        let length = (nx * nx + ny * ny).sqrt();
+        let (nx, ny) = if length == 0.0 {
+            (0.0, 0.0)
+        } else {
+            (nx / length, ny / length)
+        };
+        // Push the corners of sharp turns out so the segments keep their width,
+        // but not without limit.
+        let segment = if i > 0 { before } else { after };
+        let cos = (nx * segment.0 + ny * segment.1).max(0.25);
+        let offset = width / 2.0 / cos;
+        for &side in &[1.0, -1.0] {
+            let pos = [p.x + nx * offset * side, p.y + ny * offset * side];
+            buffer.vertices.push(Vertex {
+                                     pos: pos,
+                                     uv: pos,
+                                     color: [1.0, 1.0, 1.0, 1.0],
+                                 });
+        }
+        if i < last {
+            let i = i as u16 * 2;
+            buffer.indices.extend_from_slice(&[i, i + 1, i + 2, i + 1, i + 3, i + 2]);
+        }
+    }
+    Ok(buffer)
+}
+
+pub fn build_polygon_fill(points: &[Point], tolerance: f32) -> GameResult<Buffer> {
     let path = build_path(points, true);
-    let path_iter = path.path_iter().flattened(FLATTEN_TOLERANCE);
+    let path_iter = path.path_iter().flattened(tolerance);
     let opts = path_fill::FillOptions::default();
     let mut tessellator = path_fill::FillTessellator::new();
     build_geometry(0.0, |builder| tessellator.tessellate_path(path_iter, &opts, builder))
 }
 
+/// Build a cubic bezier curve from `start` to `end`.
+pub fn build_bezier(start: Point,
+                    control1: Point,
+                    control2: Point,
+                    end: Point,
+                    options: &StrokeOptions,
+                    tolerance: f32)
+                    -> GameResult<Buffer> {
+    let mut path_builder = path::Builder::new();
+    path_builder.move_to(math::point(start.x, start.y));
+    path_builder.cubic_bezier_to(math::point(control1.x, control1.y),
+                                 math::point(control2.x, control2.y),
+                                 math::point(end.x, end.y));
+    stroke_path(path_builder.build(), options, tolerance)
+}
+
+/// Build a quadratic bezier curve from `start` to `end`.
+pub fn build_quadratic_bezier(start: Point,
+                              control: Point,
+                              end: Point,
+                              options: &StrokeOptions,
+                              tolerance: f32)
+                              -> GameResult<Buffer> {
+    let mut path_builder = path::Builder::new();
+    path_builder.move_to(math::point(start.x, start.y));
+    path_builder.quadratic_bezier_to(math::point(control.x, control.y),
+                                     math::point(end.x, end.y));
+    stroke_path(path_builder.build(), options, tolerance)
+}
+
+/// Returns `segments + 1` points evenly spaced along an elliptical arc,
+/// from `angle1` to `angle2` in radians, including both ends.
+pub fn arc_points(center: Point,
+                  r1: f32,
+                  r2: f32,
+                  angle1: f32,
+                  angle2: f32,
+                  segments: u32)
+                  -> Vec<Point> {
+    let segments = segments.max(1);
+    let step = (angle2 - angle1) / segments as f32;
+    (0..segments + 1)
+        .map(|i| {
+                 let angle = angle1 + step * i as f32;
+                 Point::new(center.x + r1 * angle.cos(), center.y + r2 * angle.sin())
+             })
+        .collect()
+}
+
+/// Returns how many segments an arc of the given radius and angle
+/// needs to stay within `tolerance` of the real curve.
+pub fn arc_segments(radius: f32, angle: f32, tolerance: f32) -> u32 {
+    if radius <= tolerance {
+        return 1;
+    }
+    // Each segment cuts across the curve, at most `tolerance` inside it.
+    let max_step = 2.0 * (1.0 - tolerance / radius).acos();
+    (angle.abs() / max_step).ceil().max(1.0) as u32
+}
+
+/// Returns the outline of a rectangle with rounded corners, going
+/// clockwise from the top-left corner.
+pub fn rounded_rectangle_points(x1: f32,
+                                y1: f32,
+                                x2: f32,
+                                y2: f32,
+                                radius: f32,
+                                tolerance: f32)
+                                -> Vec<Point> {
+    let radius = radius.min((x2 - x1).abs() / 2.0).min((y2 - y1).abs() / 2.0).max(0.0);
+    if radius == 0.0 {
+        return vec![Point::new(x1, y1), Point::new(x2, y1), Point::new(x2, y2), Point::new(x1, y2)];
+    }
+    let segments = arc_segments(radius, PI / 2.0, tolerance);
+    let corners = [(x1 + radius, y1 + radius, PI),
+                   (x2 - radius, y1 + radius, PI * 1.5),
+                   (x2 - radius, y2 - radius, 0.0),
+                   (x1 + radius, y2 - radius, PI / 2.0)];
+    let mut points = Vec::with_capacity(corners.len() * (segments as usize + 1));
+    for &(cx, cy, start) in &corners {
+        points.extend(arc_points(Point::new(cx, cy),
+                                 radius,
+                                 radius,
+                                 start,
+                                 start + PI / 2.0,
+                                 segments));
+    }
+    // Straight edges of zero length leave the same point twice.
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+pub fn build_ellipse(point: Point,
+                     r1: f32,
+                     r2: f32,
+                     segments: u32,
+                     options: &StrokeOptions,
+                     tolerance: f32)
+                     -> GameResult<Buffer> {
+    let mut points = arc_points(point, r1, r2, 0.0, 2.0 * PI, segments);
+    // The last point is the same as the first; closing the path joins them.
+    points.pop();
+    build_polygon(&points, options, tolerance)
+}
+
 pub fn build_ellipse_fill(point: Point, r1: f32, r2: f32, segments: u32) -> GameResult<Buffer> {
     let center = math::point(point.x, point.y);
     let radii = math::point(r1, r2);
@@ -106,3 +399,205 @@ pub fn build_ellipse_fill(point: Point, r1: f32, r2: f32, segments: u32) -> Game
         Ok::<_, ()>(count)
     })
 }
+
+/// Returns a buffer of the given triangles, three points each;
+/// any leftover points are ignored.
+pub fn build_triangles(triangles: &[Point]) -> GameResult<Buffer> {
+    let count = triangles.len() - triangles.len() % 3;
+    if count > u16::MAX as usize + 1 {
+        let msg = String::from("Too many vertices for one mesh; they have to fit in a u16");
+        return Err(GameError::RenderError(msg));
+    }
+    let mut buffer = Buffer::new();
+    buffer.vertices = triangles[..count]
+        .iter()
+        .map(|p| {
+                 Vertex {
+                     pos: [p.x, p.y],
+                     uv: [p.x, p.y],
+                     color: [1.0, 1.0, 1.0, 1.0],
+                 }
+             })
+        .collect();
+    buffer.indices = (0..count).map(|i| i as u16).collect();
+    Ok(buffer)
+}
+
+/// Sets the texture coordinates of the vertices to run from 0 to 1
+/// across their bounding box, so that an `Image` is stretched over
+/// the whole of it.
+pub fn normalize_uvs(vertices: &mut [Vertex]) {
+    let (mut min, mut max) = ([::std::f32::MAX; 2], [::std::f32::MIN; 2]);
+    for v in vertices.iter() {
+        for i in 0..2 {
+            min[i] = min[i].min(v.pos[i]);
+            max[i] = max[i].max(v.pos[i]);
+        }
+    }
+    for v in vertices.iter_mut() {
+        for i in 0..2 {
+            let size = max[i] - min[i];
+            v.uv[i] = if size > 0.0 {
+                (v.pos[i] - min[i]) / size
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+/// Adds the contents of `src` to the end of `dest`, setting the color
+/// of each vertex to what `color_at` returns for its position.
+pub fn append_buffer<F>(dest: &mut Buffer, src: Buffer, color_at: F) -> GameResult<()>
+    where F: Fn([f32; 2]) -> [f32; 4]
+{
+    let offset = dest.vertices.len();
+    if offset + src.vertices.len() > u16::MAX as usize + 1 {
+        let msg = String::from("Too many vertices for one mesh; they have to fit in a u16");
+        return Err(GameError::RenderError(msg));
+    }
+    dest.vertices
+        .extend(src.vertices
+                    .into_iter()
+                    .map(|v| Vertex { color: color_at(v.pos), ..v }));
+    dest.indices.extend(src.indices.into_iter().map(|i| i + offset as u16));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arc_points() {
+        let center = Point::new(10.0, 10.0);
+        let points = arc_points(center, 5.0, 5.0, 0.0, PI, 2);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0], Point::new(15.0, 10.0));
+        assert!((points[1].x - 10.0).abs() < 1e-4 && (points[1].y - 15.0).abs() < 1e-4);
+        assert!((points[2].x - 5.0).abs() < 1e-4 && (points[2].y - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_normalize_uvs() {
+        let opts = StrokeOptions::with_width(4.0);
+        let mut buffer = build_ellipse(Point::new(100.0, 50.0), 30.0, 20.0, 16, &opts, 0.5)
+            .unwrap();
+        normalize_uvs(&mut buffer.vertices);
+        for v in &buffer.vertices {
+            assert!(v.uv[0] >= 0.0 && v.uv[0] <= 1.0);
+            assert!(v.uv[1] >= 0.0 && v.uv[1] <= 1.0);
+        }
+        assert!(buffer.vertices.iter().any(|v| v.uv[0] == 0.0));
+        assert!(buffer.vertices.iter().any(|v| v.uv[1] == 1.0));
+
+        // A flat shape doesn't divide by zero.
+        let mut buffer = build_triangles(&[Point::new(0.0, 0.0); 3]).unwrap();
+        normalize_uvs(&mut buffer.vertices);
+        assert_eq!(buffer.vertices[0].uv, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_append_buffer() {
+        let red = [1.0, 0.0, 0.0, 1.0];
+        let triangle = [Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(0.0, 1.0)];
+        let mut buffer = build_triangles(&triangle).unwrap();
+        append_buffer(&mut buffer, build_triangles(&triangle).unwrap(), |_| red).unwrap();
+        assert_eq!(buffer.vertices.len(), 6);
+        assert_eq!(buffer.indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(buffer.vertices[0].color, [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(buffer.vertices[5].color, red);
+
+        // 65536 points is 21845 triangles with one point left over,
+        // and each index still fits.
+        let many = vec![Point::new(0.0, 0.0); 65536];
+        let buffer = build_triangles(&many).unwrap();
+        assert_eq!(buffer.indices.len(), 65535);
+        assert_eq!(buffer.indices.last(), Some(&65534));
+        assert!(build_triangles(&vec![Point::new(0.0, 0.0); 65539]).is_err());
+    }
+
+    #[test]
+    fn test_arc_segments() {
+        assert_eq!(arc_segments(0.1, PI, 0.5), 1);
+        let coarse = arc_segments(100.0, PI, 1.0);
+        let fine = arc_segments(100.0, PI, 0.1);
+        assert!(coarse > 1);
+        assert!(fine > coarse);
+    }
+
+    #[test]
+    fn test_rounded_rectangle_points() {
+        assert_eq!(rounded_rectangle_points(0.0, 0.0, 10.0, 10.0, 0.0, 0.5),
+                   vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(10.0, 10.0),
+                        Point::new(0.0, 10.0)]);
+
+        // Too big a radius is cut down to half the smaller side,
+        // which makes a circle.
+        let points = rounded_rectangle_points(0.0, 0.0, 10.0, 10.0, 8.0, 0.5);
+        assert!(points.len() > 4);
+        for p in &points {
+            let distance = ((p.x - 5.0).powi(2) + (p.y - 5.0).powi(2)).sqrt();
+            assert!((distance - 5.0).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_dash_points() {
+        let line = [Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(10.0, 4.0)];
+        let pieces = dash_points(&line, false, &[4.0, 2.0], false);
+        assert_eq!(pieces,
+                   vec![vec![Point::new(0.0, 0.0), Point::new(4.0, 0.0)],
+                        vec![Point::new(6.0, 0.0), Point::new(10.0, 0.0)],
+                        vec![Point::new(10.0, 2.0), Point::new(10.0, 4.0)]]);
+
+        // A dash going round a corner keeps the corner.
+        let pieces = dash_points(&line, false, &[12.0, 1.0], false);
+        assert_eq!(pieces[0],
+                   vec![Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(10.0, 2.0)]);
+
+        // Closing the line dashes the edge back to the start too.
+        let square = [Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 2.0),
+                      Point::new(0.0, 2.0)];
+        assert_eq!(dash_points(&square, true, &[1.0, 1.0], false).len(), 4);
+
+        assert_eq!(dash_points(&line, false, &[0.0, 0.0], false), vec![line.to_vec()]);
+
+        // Zero-length dashes are dropped, or kept as dots for caps that
+        // can draw them.
+        let straight = [Point::new(0.0, 0.0), Point::new(4.0, 0.0)];
+        assert!(dash_points(&straight, false, &[0.0, 2.0], false).is_empty());
+        let dots = dash_points(&straight, false, &[0.0, 2.0], true);
+        assert_eq!(dots.len(), 2);
+        assert_eq!(dots[1], vec![Point::new(2.0, 0.0), Point::new(2.0 + DOT_LENGTH, 0.0)]);
+    }
+
+    #[test]
+    fn test_round_joins_and_caps() {
+        // Round joins and caps come from lyon, so make sure they really
+        // add the curved pieces rather than quietly falling back.
+        let line = [Point::new(0.0, 0.0), Point::new(10.0, 0.0), Point::new(10.0, 10.0)];
+        let vertices = |join, cap| {
+            let options = StrokeOptions {
+                join: join,
+                cap: cap,
+                ..StrokeOptions::with_width(4.0)
+            };
+            build_line(&line, &options, 0.01).unwrap().vertices.len()
+        };
+        let plain = vertices(LineJoin::Miter, LineCap::Butt);
+        assert!(vertices(LineJoin::Round, LineCap::Butt) > plain);
+        assert!(vertices(LineJoin::Miter, LineCap::Round) > plain);
+    }
+
+    #[test]
+    fn test_build_variable_line() {
+        let line = [Point::new(0.0, 0.0), Point::new(10.0, 0.0)];
+        let buffer = build_variable_line(&line, &[1.0, 3.0]).unwrap();
+        assert_eq!(buffer.vertices.len(), 4);
+        assert_eq!(buffer.indices, vec![0, 1, 2, 1, 3, 2]);
+        assert_eq!(buffer.vertices[0].pos, [0.0, 0.5]);
+        assert_eq!(buffer.vertices[3].pos, [10.0, -1.5]);
+        assert!(build_variable_line(&line, &[1.0]).is_err());
+    }
+}
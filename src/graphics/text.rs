@@ -3,30 +3,79 @@ use std::fmt;
 use std::path;
 use std::collections::BTreeMap;
 use std::io::Read;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 
 use rusttype;
 use image;
 
 use super::*;
+use super::bmfont;
+
+/// Each TTF font gets its own ID, which the glyph cache tells
+/// glyphs from different fonts apart by.
+static NEXT_FONT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
 
 /// A font that defines the shape of characters drawn on the screen.
-/// Can be created from a .ttf file or from an image (bitmap fonts).
+/// Can be created from a .ttf file or from an image (bitmap fonts),
+/// or made out of several other fonts.
 pub enum Font {
     TTFFont {
         font: rusttype::Font<'static>,
         points: u32,
         scale: rusttype::Scale,
+        id: usize,
     },
     BitmapFont {
-        // Width, height and data for the original glyph image.
+        // Width, height and data for the original glyph image,
+        // with multiple pages one above the other.
         // This is always going to be RGBA.
         bytes: Vec<u8>,
         width: usize,
         height: usize,
-        // Glyph to index mapping
-        glyphs: BTreeMap<char, usize>,
-        glyph_width: usize,
+        // Where each glyph is in the image.
+        glyphs: BTreeMap<char, BitmapGlyph>,
+        // How much closer together to move pairs of glyphs; usually negative.
+        kerning: BTreeMap<(char, char), i32>,
+        line_height: usize,
+        // The distance from the top of a line to its baseline.
+        base: usize,
     },
+    /// Several fonts used together, made with `Font::new_font_set()`.
+    FontSet { fonts: Vec<Font> },
+}
+
+/// Where a glyph of a bitmap font is in the font's image,
+/// and how it's placed relative to the other glyphs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BitmapGlyph {
+    /// The left edge of the glyph in the image, in pixels.
+    pub x: usize,
+    /// The top edge of the glyph in the image, in pixels.
+    pub y: usize,
+    /// The width of the glyph in the image, in pixels.
+    pub width: usize,
+    /// The height of the glyph in the image, in pixels.
+    pub height: usize,
+    /// How far right of the pen position to draw the glyph.
+    pub x_offset: i32,
+    /// How far below the top of the line to draw the glyph.
+    pub y_offset: i32,
+    /// How far to move the pen right after drawing the glyph.
+    pub advance: i32,
+}
+
+/// The vertical measurements of a `Font`, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct FontMetrics {
+    /// How far the font goes above the baseline.
+    pub ascent: f32,
+    /// How far the font goes below the baseline; this is negative.
+    pub descent: f32,
+    /// The space left between the descent of one line
+    /// and the ascent of the next.
+    pub line_gap: f32,
 }
 
 impl Font {
@@ -62,6 +111,7 @@ impl Font {
                font: font,
                points: points,
                scale: scale,
+               id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
            })
     }
 
@@ -73,27 +123,87 @@ impl Font {
                                             path: P,
                                             glyphs: &str)
                                             -> GameResult<Font> {
-        let img = {
+        let img = load_rgba(context, path.as_ref())?;
+        let (image_width, image_height) = img.dimensions();
+        let glyph_count = cmp::max(glyphs.chars().count(), 1);
+        let glyph_width = (image_width as usize) / glyph_count;
+        bitmap_grid(img, glyphs, glyph_width, image_height as usize)
+    }
+
+    /// Loads an `Image` and uses it to create a new bitmap font out of a
+    /// grid of glyphs, each `glyph_width` by `glyph_height` pixels.
+    /// The `glyphs` string is the characters in the image, from left to
+    /// right along each row and then from the top row to the bottom one.
+    pub fn new_bitmap_grid<P: AsRef<path::Path>>(context: &mut Context,
+                                                 path: P,
+                                                 glyphs: &str,
+                                                 glyph_width: usize,
+                                                 glyph_height: usize)
+                                                 -> GameResult<Font> {
+        let img = load_rgba(context, path.as_ref())?;
+        bitmap_grid(img, glyphs, glyph_width, glyph_height)
+    }
+
+    /// Loads a bitmap font from an AngelCode BMFont `.fnt` file, in either
+    /// its text or binary format, as made by BMFont and many other tools.
+    /// The page images it names are loaded from next to it.
+    pub fn new_bmfont<P: AsRef<path::Path>>(context: &mut Context, path: P) -> GameResult<Font> {
+        let path = path.as_ref();
+        let descriptor = {
             let mut buf = Vec::new();
             let mut reader = context.filesystem.open(path)?;
             reader.read_to_end(&mut buf)?;
-            image::load_from_memory(&buf)?.to_rgba()
+            bmfont::parse(&buf)?
         };
-        let (image_width, image_height) = img.dimensions();
 
-        let glyph_width = (image_width as usize) / glyphs.len();
-        // println!("Number of glyphs: {}, Glyph width: {}, image width: {}",
-        // glyphs.len(), glyph_width, image_width);
-        let mut glyphs_map: BTreeMap<char, usize> = BTreeMap::new();
-        for (i, c) in glyphs.chars().enumerate() {
-            glyphs_map.insert(c, i * glyph_width);
+        let mut pages = Vec::with_capacity(descriptor.pages.len());
+        for page in &descriptor.pages {
+            pages.push(load_rgba(context, &path.with_file_name(page))?);
+        }
+        // Stack the pages on top of each other in one image.
+        let width = pages.iter().map(|p| p.width() as usize).max().unwrap_or(0);
+        let height = pages.iter().map(|p| p.height() as usize).sum();
+        let mut bytes = vec![0; width * height * 4];
+        let mut page_tops = Vec::with_capacity(pages.len());
+        let mut top = 0;
+        for page in &pages {
+            let (page_width, page_height) = (page.width() as usize, page.height() as usize);
+            blit(&mut bytes,
+                 (width, height),
+                 (0, top),
+                 page,
+                 (page_width, page_height),
+                 (0, 0),
+                 (page_width, page_height),
+                 4);
+            page_tops.push(top);
+            top += page_height;
+        }
+
+        let mut glyphs = BTreeMap::new();
+        for &(c, page, glyph) in &descriptor.glyphs {
+            let fits = pages
+                .get(page)
+                .map_or(false, |p| glyph_fits(&glyph, p.width() as usize, p.height() as usize));
+            if !fits {
+                let msg = format!("Glyph {:?} in {:?} is outside of its page", c, path);
+                return Err(GameError::ResourceLoadError(msg));
+            }
+            glyphs.insert(c, BitmapGlyph { y: glyph.y + page_tops[page], ..glyph });
         }
+        let kerning = descriptor
+            .kerning
+            .iter()
+            .map(|&(first, second, amount)| ((first, second), amount))
+            .collect();
         Ok(Font::BitmapFont {
-               bytes: img.into_vec(),
-               width: image_width as usize,
-               height: image_height as usize,
-               glyphs: glyphs_map,
-               glyph_width: glyph_width,
+               bytes: bytes,
+               width: width,
+               height: height,
+               glyphs: glyphs,
+               kerning: kerning,
+               line_height: descriptor.line_height,
+               base: descriptor.base,
            })
     }
 
@@ -107,13 +217,135 @@ impl Font {
         Font::from_bytes("default", &buf[..], size, (75.0, 75.0))
     }
 
+    /// Makes a font out of several others, which draws each character
+    /// with the first of them that has it.  This lets text fall back to
+    /// other fonts for characters the main one is missing, for instance
+    /// CJK characters or emoji.  Characters none of them have are drawn
+    /// with the first font.
+    pub fn new_font_set(fonts: Vec<Font>) -> GameResult<Font> {
+        if fonts.is_empty() {
+            return Err(GameError::FontError(String::from("A font set needs at least one font")));
+        }
+        Ok(Font::FontSet { fonts: fonts })
+    }
+
+    /// Returns whether the font has a glyph for the given character.
+    pub fn has_glyph(&self, c: char) -> bool {
+        match *self {
+            // Characters a TTF font doesn't have get glyph 0, the "missing" box.
+            Font::TTFFont { ref font, .. } => font.glyph(c).map_or(false, |g| g.id().0 != 0),
+            Font::BitmapFont { ref glyphs, .. } => glyphs.contains_key(&c),
+            Font::FontSet { ref fonts } => fonts.iter().any(|f| f.has_glyph(c)),
+        }
+    }
+
+    /// Returns the vertical measurements of the font, in pixels.
+    pub fn get_metrics(&self) -> FontMetrics {
+        match *self {
+            Font::TTFFont { ref font, scale, .. } => {
+                let v_metrics = font.v_metrics(scale);
+                FontMetrics {
+                    ascent: v_metrics.ascent,
+                    descent: v_metrics.descent,
+                    line_gap: v_metrics.line_gap,
+                }
+            }
+            Font::BitmapFont { line_height, base, .. } => {
+                FontMetrics {
+                    ascent: base as f32,
+                    descent: base as f32 - line_height as f32,
+                    line_gap: 0.0,
+                }
+            }
+            Font::FontSet { ref fonts } => {
+                let metrics = fonts.iter().map(|f| f.get_metrics());
+                metrics.fold(FontMetrics::default(), |a, b| {
+                    FontMetrics {
+                        ascent: a.ascent.max(b.ascent),
+                        descent: a.descent.min(b.descent),
+                        line_gap: a.line_gap.max(b.line_gap),
+                    }
+                })
+            }
+        }
+    }
+
+    /// Returns how far drawing the given character moves the pen
+    /// along, in pixels, not counting kerning.
+    pub fn get_advance(&self, c: char) -> f32 {
+        match *self {
+            Font::TTFFont { ref font, scale, .. } => {
+                font.glyph(c).map_or(0.0, |g| g.scaled(scale).h_metrics().advance_width)
+            }
+            Font::BitmapFont { ref glyphs, .. } => {
+                glyphs.get(&c).map_or(0.0, |g| g.advance as f32)
+            }
+            Font::FontSet { ref fonts } => {
+                fallback_font(fonts, c).map_or(0.0, |f| f.get_advance(c))
+            }
+        }
+    }
+
+    /// Returns the box the given character covers when drawn with
+    /// the pen at the origin, on the baseline; the top of the box is
+    /// negative, since it's above the baseline.  Returns `None` if the
+    /// character doesn't cover anything, like a space.
+    pub fn get_glyph_bounds(&self, c: char) -> Option<Rect> {
+        match *self {
+            Font::TTFFont { ref font, scale, .. } => {
+                let origin = rusttype::point(0.0, 0.0);
+                font.glyph(c)
+                    .and_then(|g| g.scaled(scale).positioned(origin).pixel_bounding_box())
+                    .map(|bb| {
+                             Rect::new(bb.min.x as f32,
+                                       bb.min.y as f32,
+                                       (bb.max.x - bb.min.x) as f32,
+                                       (bb.max.y - bb.min.y) as f32)
+                         })
+            }
+            Font::BitmapFont { ref glyphs, base, .. } => {
+                match glyphs.get(&c) {
+                    Some(g) if g.width > 0 && g.height > 0 => {
+                        Some(Rect::new(g.x_offset as f32,
+                                       (g.y_offset - base as i32) as f32,
+                                       g.width as f32,
+                                       g.height as f32))
+                    }
+                    _ => None,
+                }
+            }
+            Font::FontSet { ref fonts } => {
+                fallback_font(fonts, c).and_then(|f| f.get_glyph_bounds(c))
+            }
+        }
+    }
+
+    /// Returns how much the pen moves along between the two characters
+    /// when they're drawn next to each other, on top of the first one's
+    /// advance.  This is usually negative, to tuck them closer together.
+    pub fn get_kerning(&self, first: char, second: char) -> f32 {
+        match *self {
+            Font::TTFFont { ref font, scale, .. } => font.pair_kerning(scale, first, second),
+            Font::BitmapFont { ref kerning, .. } => {
+                kerning.get(&(first, second)).cloned().unwrap_or(0) as f32
+            }
+            Font::FontSet { ref fonts } => {
+                match (fallback_font(fonts, first), fallback_font(fonts, second)) {
+                    (Some(a), Some(b)) if ptr::eq(a, b) => a.get_kerning(first, second),
+                    _ => 0.0,
+                }
+            }
+        }
+    }
+
     /// Get the height of the Font in pixels.
     ///
     /// The height of the font includes any spacing, it will be the total height
     /// a line needs.
     pub fn get_height(&self) -> usize {
         match *self {
-            Font::BitmapFont{ height, .. } => height,
+            Font::BitmapFont{ line_height, .. } => line_height,
+            Font::FontSet{ ref fonts } => fonts.iter().map(|f| f.get_height()).max().unwrap_or(0),
             Font::TTFFont{ scale, .. } => {
                 // let v_metrics = font.v_metrics(scale);
                 // v_metrics.
@@ -130,7 +362,15 @@ impl Font {
     /// Does not handle line-breaks.
     pub fn get_width(&self, text: &str) -> usize {
         match *self {
-            Font::BitmapFont{ width, .. } => width * text.len(),
+            Font::BitmapFont{ ref glyphs, ref kerning, .. } => {
+                cmp::max(bitmap_advance(glyphs, kerning, text), 0) as usize
+            },
+            Font::FontSet{ ref fonts } => {
+                fallback_runs(fonts, text)
+                    .iter()
+                    .map(|&(font, ref run)| font.get_width(run))
+                    .sum()
+            },
             Font::TTFFont{ref font, scale, .. } => {
                 let v_metrics = font.v_metrics(scale);
                 let offset = rusttype::point(0.0, v_metrics.ascent);
@@ -216,14 +456,153 @@ impl fmt::Debug for Font {
         match *self {
             Font::TTFFont { .. } => write!(f, "<TTFFont: {:p}>", &self),
             Font::BitmapFont { .. } => write!(f, "<BitmapFont: {:p}>", &self),
+            Font::FontSet { ref fonts } => write!(f, "<FontSet: {:?}, {:p}>", fonts, &self),
         }
     }
 }
 
 /// Drawable text created from a `Font`.
 pub struct Text {
-    texture: Image,
+    rendered: Rendered,
     contents: String,
+    padding: u32,
+    sdf: bool,
+}
+
+/// What a `Text` draws.
+enum Rendered {
+    /// A texture of its own, with the text rendered into it.
+    Texture(Image),
+    /// Glyphs drawn out of the glyph cache, with the size of the area
+    /// they cover and the filter mode to draw them with.
+    Cached {
+        glyphs: Vec<QueuedGlyph>,
+        width: u32,
+        height: u32,
+        filter: FilterMode,
+    },
+}
+
+/// How the lines of a multi-line `Text` line up with each other.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    /// Lines start at the left edge.
+    Left,
+    /// Lines are centered.
+    Center,
+    /// Lines end at the right edge.
+    Right,
+    /// Lines are stretched to fill the whole width, by widening the
+    /// spaces between words.  The last line of each paragraph is
+    /// aligned left.
+    Justify,
+}
+
+/// A piece of text with its own color, font and scale, so one `Text`
+/// can be built out of several differently styled parts with
+/// `Text::from_fragments()`.  Anything left as `None` uses the
+/// `Text`'s font, white, and a scale of 1.
+///
+/// ```rust,ignore
+/// let red = graphics::Color::new(1.0, 0.0, 0.0, 1.0);
+/// let fragments = [TextFragment::new("Game over! ").color(red),
+///                  TextFragment::new("Press any key").scale(0.5)];
+/// let text = graphics::Text::from_fragments(ctx, &fragments, &font, Some(300), Align::Center)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TextFragment<'a> {
+    /// The text itself.
+    pub text: String,
+    /// The color of the text.
+    pub color: Option<Color>,
+    /// The font to draw the text in.
+    pub font: Option<&'a Font>,
+    /// How much to scale the font by.
+    pub scale: Option<f32>,
+}
+
+impl<'a> TextFragment<'a> {
+    /// Creates a new fragment of the given text, with the default style.
+    pub fn new<S: Into<String>>(text: S) -> Self {
+        TextFragment {
+            text: text.into(),
+            color: None,
+            font: None,
+            scale: None,
+        }
+    }
+
+    /// Sets the color of the fragment.
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Sets the font of the fragment.
+    pub fn font(mut self, font: &'a Font) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    /// Sets the scale of the fragment's font.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+}
+
+impl<'a, 'b> From<&'b str> for TextFragment<'a> {
+    fn from(text: &'b str) -> Self {
+        TextFragment::new(text)
+    }
+}
+
+impl<'a> From<String> for TextFragment<'a> {
+    fn from(text: String) -> Self {
+        TextFragment::new(text)
+    }
+}
+
+/// An outline around the characters of a `Text`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Outline {
+    /// How far the outline reaches out from the characters, in pixels.
+    pub width: f32,
+    /// The color of the outline.
+    pub color: Color,
+}
+
+/// A drop shadow behind a `Text`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Shadow {
+    /// How far the shadow is moved from the text, in pixels.
+    pub offset: Point,
+    /// The color of the shadow, which is usually partly transparent.
+    pub color: Color,
+    /// How many pixels the shadow's edges are blurred over.
+    pub blur: f32,
+}
+
+/// Effects to render a `Text` with, for `Text::new_with_options()`.
+/// Each effect needs some room around the text, so the `Text` ends up
+/// bigger than it would be without them; see `Text::padding()`.
+///
+/// The effects are worked out on the CPU when the `Text` is made.  An
+/// outline or distance field takes time in proportion to the area of
+/// the text times the square of its width or spread, and a blur to the
+/// area times the blur, so wide ones on big text can take a while.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TextOptions {
+    /// An outline, which is drawn behind the characters.
+    pub outline: Option<Outline>,
+    /// A shadow, which is drawn behind the outline too if there is one.
+    pub shadow: Option<Shadow>,
+    /// Renders the text as a signed distance field reaching this many
+    /// pixels out from the edges of the characters, which stays crisp
+    /// when scaled up through `DrawParam::scale` instead of blurring.
+    /// A few pixels are usually plenty.  Blurred shadows don't survive
+    /// this, and end up with hard edges.
+    pub sdf: Option<f32>,
 }
 
 /// Compute a scale for a font of a given size.
@@ -243,6 +622,155 @@ fn display_independent_scale(points: u32, dpi_w: f32, dpi_h: f32) -> rusttype::S
     }
 }
 
+fn load_rgba(context: &mut Context, path: &path::Path) -> GameResult<image::RgbaImage> {
+    let mut buf = Vec::new();
+    let mut reader = context.filesystem.open(path)?;
+    reader.read_to_end(&mut buf)?;
+    Ok(image::load_from_memory(&buf)?.to_rgba())
+}
+
+/// Makes a bitmap font out of an image holding a grid of glyphs,
+/// each `glyph_width` by `glyph_height` pixels, in the order they're
+/// in `glyphs` going across each row in turn.
+fn bitmap_grid(img: image::RgbaImage,
+               glyphs: &str,
+               glyph_width: usize,
+               glyph_height: usize)
+               -> GameResult<Font> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    if glyph_width == 0 || glyph_height == 0 {
+        let msg = format!("Bitmap font glyphs can't be {}x{}", glyph_width, glyph_height);
+        return Err(GameError::FontError(msg));
+    }
+    let columns = width / glyph_width;
+    let rows = height / glyph_height;
+    let glyph_count = glyphs.chars().count();
+    if glyph_count > columns * rows {
+        let msg = format!("A {}x{} image only has room for {} glyphs of {}x{}, not {}",
+                          width,
+                          height,
+                          columns * rows,
+                          glyph_width,
+                          glyph_height,
+                          glyph_count);
+        return Err(GameError::FontError(msg));
+    }
+    let glyphs = glyphs
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+                 let glyph = BitmapGlyph {
+                     x: (i % columns) * glyph_width,
+                     y: (i / columns) * glyph_height,
+                     width: glyph_width,
+                     height: glyph_height,
+                     x_offset: 0,
+                     y_offset: 0,
+                     advance: glyph_width as i32,
+                 };
+                 (c, glyph)
+             })
+        .collect();
+    Ok(Font::BitmapFont {
+           bytes: img.into_raw(),
+           width: width,
+           height: height,
+           glyphs: glyphs,
+           kerning: BTreeMap::new(),
+           line_height: glyph_height,
+           base: glyph_height,
+       })
+}
+
+/// Returns whether a bitmap font glyph lies within a page of the given
+/// size.  Its position comes straight from the font file, so it could
+/// be anything.
+fn glyph_fits(glyph: &BitmapGlyph, width: usize, height: usize) -> bool {
+    match (glyph.x.checked_add(glyph.width), glyph.y.checked_add(glyph.height)) {
+        (Some(right), Some(bottom)) => right <= width && bottom <= height,
+        _ => false,
+    }
+}
+
+/// Returns how far the given text moves the pen in a bitmap font,
+/// in pixels.  Characters that aren't in the font are skipped.
+fn bitmap_advance(glyphs: &BTreeMap<char, BitmapGlyph>,
+                  kerning: &BTreeMap<(char, char), i32>,
+                  text: &str)
+                  -> i32 {
+    let mut pen = 0;
+    let mut previous = None;
+    for c in text.chars() {
+        if let Some(p) = previous {
+            pen += kerning.get(&(p, c)).cloned().unwrap_or(0);
+        }
+        pen += glyphs.get(&c).map_or(0, |g| g.advance);
+        previous = Some(c);
+    }
+    pen
+}
+
+/// Returns a TTF font's scale multiplied by `scale`.
+fn scaled(s: rusttype::Scale, scale: f32) -> rusttype::Scale {
+    rusttype::Scale {
+        x: s.x * scale,
+        y: s.y * scale,
+    }
+}
+
+/// Lays out a line of text in a TTF font, kerned, starting from `start`
+/// on the baseline.  Returns the glyphs and where the pen ends up.
+fn layout_ttf<'a>(font: &rusttype::Font<'a>,
+                  scale: rusttype::Scale,
+                  text: &str,
+                  start: rusttype::Point<f32>)
+                  -> (Vec<rusttype::PositionedGlyph<'a>>, f32) {
+    let mut glyphs = Vec::with_capacity(text.len());
+    let mut caret = start.x;
+    let mut previous = None;
+    for c in text.chars() {
+        let glyph = match font.glyph(c) {
+            Some(glyph) => glyph.scaled(scale),
+            None => continue,
+        };
+        if let Some(previous) = previous {
+            caret += font.pair_kerning(scale, previous, glyph.id());
+        }
+        previous = Some(glyph.id());
+        let advance = glyph.h_metrics().advance_width;
+        glyphs.push(glyph.positioned(rusttype::point(caret, start.y)));
+        caret += advance;
+    }
+    (glyphs, caret)
+}
+
+/// Returns the first of `fonts` that has the given character, or the
+/// first font if none of them do.
+fn fallback_font(fonts: &[Font], c: char) -> Option<&Font> {
+    fonts.iter().find(|f| f.has_glyph(c)).or_else(|| fonts.first())
+}
+
+/// Splits text into runs that are each drawn with one of `fonts`,
+/// as `Font::new_font_set()` describes.
+fn fallback_runs<'a>(fonts: &'a [Font], text: &str) -> Vec<(&'a Font, String)> {
+    let mut runs: Vec<(&Font, String)> = Vec::new();
+    for c in text.chars() {
+        let font = match fallback_font(fonts, c) {
+            Some(font) => font,
+            None => break,
+        };
+        match runs.last_mut() {
+            Some(&mut (last, ref mut run)) if ptr::eq(last, font) => {
+                run.push(c);
+                continue;
+            }
+            _ => (),
+        }
+        runs.push((font, c.to_string()));
+    }
+    runs
+}
+
 fn text_width(glyphs: &[rusttype::PositionedGlyph]) -> f32 {
     glyphs
         .iter()
@@ -257,81 +785,6 @@ fn text_width(glyphs: &[rusttype::PositionedGlyph]) -> f32 {
         .unwrap_or(0.0)
 }
 
-fn render_ttf(context: &mut Context,
-              text: &str,
-              font: &rusttype::Font<'static>,
-              scale: rusttype::Scale)
-              -> GameResult<Text> {
-    // Ripped almost wholesale from
-    // https://github.com/dylanede/rusttype/blob/master/examples/simple.rs
-
-    let text_height_pixels = scale.y.ceil() as usize;
-    let v_metrics = font.v_metrics(scale);
-    let offset = rusttype::point(0.0, v_metrics.ascent);
-    // Then turn them into an array of positioned glyphs...
-    // `layout()` turns an abstract glyph, which contains no concrete
-    // size or position information, into a PositionedGlyph, which does.
-    let glyphs: Vec<rusttype::PositionedGlyph> = font.layout(text, scale, offset).collect();
-    let text_width_pixels = text_width(&glyphs).ceil() as usize;
-    // let text_width_pixels = glyphs
-    //     .iter()
-    //     .rev()
-    //     .filter_map(|g| {
-    //                     g.pixel_bounding_box()
-    //                         .map(|b| {
-    //                                  b.min.x as f32 + g.unpositioned().h_metrics().advance_width
-    //                              })
-    //                 })
-    //     .next()
-    //     .unwrap_or(0.0)
-    //     .ceil() as usize;
-    // // Make an array for our rendered bitmap
-    let bytes_per_pixel = 4;
-    let mut pixel_data = vec![0; text_width_pixels * text_height_pixels * bytes_per_pixel];
-    let pitch = text_width_pixels * bytes_per_pixel;
-
-    // Now we actually render the glyphs to a bitmap...
-    for g in glyphs {
-        if let Some(bb) = g.pixel_bounding_box() {
-            // v is the amount of the pixel covered
-            // by the glyph, in the range 0.0 to 1.0
-            g.draw(|x, y, v| {
-                let c = (v * 255.0) as u8;
-                let x = x as i32 + bb.min.x;
-                let y = y as i32 + bb.min.y;
-                // There's still a possibility that the glyph clips the boundaries of the bitmap
-                if x >= 0 && x < text_width_pixels as i32 && y >= 0 &&
-                   y < text_height_pixels as i32 {
-                    let x = x as usize * bytes_per_pixel;
-                    let y = y as usize;
-                    pixel_data[(x + y * pitch + 0)] = 255;
-                    pixel_data[(x + y * pitch + 1)] = 255;
-                    pixel_data[(x + y * pitch + 2)] = 255;
-                    pixel_data[(x + y * pitch + 3)] = c;
-                }
-            })
-        }
-    }
-
-    // println!("Creating text {}, {}x{}: {:?}",
-    //text, text_width_pixels, text_height_pixels, pixel_data);
-
-    // Copy the bitmap into an image, and we're basically done!
-    assert!(text_width_pixels < u16::MAX as usize);
-    assert!(text_height_pixels < u16::MAX as usize);
-    let image = Image::from_rgba8(context,
-                                  text_width_pixels as u16,
-                                  text_height_pixels as u16,
-                                  &pixel_data)?;
-
-    let text_string = text.to_string();
-    Ok(Text {
-           texture: image,
-           contents: text_string,
-       })
-
-}
-
 /// Treats src and dst as row-major 2D arrays, and blits the given rect from src to dst.
 /// Does no bounds checking or anything; if you feed it invalid bounds it will just panic.
 /// Generally, you shouldn't need to use this directly.
@@ -366,77 +819,1075 @@ fn blit(dst: &mut [u8],
     }
 }
 
-fn render_bitmap(context: &mut Context,
-                 text: &str,
-                 bytes: &[u8],
+/// A piece of text placed by `layout()`, all in one fragment's style.
+#[derive(Debug, Clone, PartialEq)]
+struct PlacedRun {
+    fragment: usize,
+    text: String,
+    x: f32,
+    baseline: f32,
+}
+
+/// A word being laid out, which may be made of pieces of several
+/// fragments with no whitespace between them.
+#[derive(Debug)]
+struct LayoutWord {
+    // Fragment, text and width of each piece.
+    pieces: Vec<(usize, String, f32)>,
+    width: f32,
+    space_before: f32,
+}
+
+#[derive(Debug)]
+struct LayoutLine {
+    words: Vec<LayoutWord>,
+    width: f32,
+    ends_paragraph: bool,
+    // Which fragment's line height an empty line gets.
+    fragment: usize,
+}
+
+impl LayoutLine {
+    fn new(fragment: usize) -> Self {
+        LayoutLine {
+            words: Vec::new(),
+            width: 0.0,
+            ends_paragraph: false,
+            fragment: fragment,
+        }
+    }
+}
+
+/// Lays out the given fragments of text in lines no wider than
+/// `wrap_limit`, if there is one, breaking them at whitespace and at
+/// newlines.  Like `Font::get_wrap()` it doesn't keep runs of whitespace.
+///
+/// With `preserve_whitespace`, the text is instead put on one line just
+/// as it is, with every space kept and newlines drawn like any other
+/// character; `wrap_limit` only sets the width then.
+///
+/// `advance` gives the width of some text in a fragment's style, and
+/// `vmetrics` gives the ascent and line height of a fragment's style.
+///
+/// Returns where each piece of text goes, and the width and height of
+/// the whole block of text.
+fn layout<A, V>(fragments: &[&str],
+                wrap_limit: Option<f32>,
+                align: Align,
+                preserve_whitespace: bool,
+                advance: A,
+                vmetrics: V)
+                -> (Vec<PlacedRun>, f32, f32)
+    where A: Fn(usize, &str) -> f32,
+          V: Fn(usize) -> (f32, f32)
+{
+    // Split the text into paragraphs of words.
+    let mut paragraphs: Vec<(Vec<LayoutWord>, usize)> = vec![(Vec::new(), 0)];
+    if preserve_whitespace {
+        // All of it as one word, which never gets broken up.
+        let pieces: Vec<(usize, String, f32)> = fragments
+            .iter()
+            .enumerate()
+            .filter(|&(_, text)| !text.is_empty())
+            .map(|(i, text)| (i, text.to_string(), 0.0))
+            .collect();
+        if !pieces.is_empty() {
+            paragraphs[0].0.push(LayoutWord {
+                                     pieces: pieces,
+                                     width: 0.0,
+                                     space_before: 0.0,
+                                 });
+        }
+    } else {
+        let mut pending_space = None;
+        let mut in_word = false;
+        for (i, text) in fragments.iter().enumerate() {
+            for c in text.chars() {
+                if c == '\n' {
+                    paragraphs.push((Vec::new(), i));
+                    pending_space = None;
+                    in_word = false;
+                } else if c.is_whitespace() {
+                    pending_space = pending_space.or(Some(i));
+                    in_word = false;
+                } else {
+                    let words = &mut paragraphs.last_mut().expect("no paragraph?").0;
+                    if !in_word {
+                        let space_before = match pending_space {
+                            Some(f) if !words.is_empty() => advance(f, " "),
+                            _ => 0.0,
+                        };
+                        words.push(LayoutWord {
+                                       pieces: Vec::new(),
+                                       width: 0.0,
+                                       space_before: space_before,
+                                   });
+                        pending_space = None;
+                        in_word = true;
+                    }
+                    let word = words.last_mut().expect("no word?");
+                    if word.pieces.last().map(|piece| piece.0) != Some(i) {
+                        word.pieces.push((i, String::new(), 0.0));
+                    }
+                    word.pieces.last_mut().expect("no piece?").1.push(c);
+                }
+            }
+        }
+    }
+
+    // Break the paragraphs into lines.
+    let mut lines = Vec::new();
+    for (words, fragment) in paragraphs {
+        let mut line = LayoutLine::new(fragment);
+        for mut word in words {
+            for piece in &mut word.pieces {
+                piece.2 = advance(piece.0, &piece.1);
+            }
+            word.width = word.pieces.iter().map(|piece| piece.2).sum();
+            if let Some(limit) = wrap_limit {
+                if !line.words.is_empty() && line.width + word.space_before + word.width > limit {
+                    let next_line = LayoutLine::new(word.pieces[0].0);
+                    lines.push(::std::mem::replace(&mut line, next_line));
+                }
+            }
+            if line.words.is_empty() {
+                word.space_before = 0.0;
+            }
+            line.width += word.space_before + word.width;
+            line.words.push(word);
+        }
+        line.ends_paragraph = true;
+        lines.push(line);
+    }
+
+    let widest = lines.iter().map(|line| line.width).fold(0.0, f32::max);
+    let width = wrap_limit.map_or(widest, |limit| limit.max(widest));
+
+    // And place each piece of each word.
+    let mut runs = Vec::new();
+    let mut y = 0.0;
+    for line in lines {
+        let (ascent, height) = if line.words.is_empty() {
+            vmetrics(line.fragment)
+        } else {
+            line.words
+                .iter()
+                .flat_map(|word| word.pieces.iter())
+                .map(|piece| vmetrics(piece.0))
+                .fold((0.0, 0.0), |(a, h), (a2, h2)| (f32::max(a, a2), f32::max(h, h2)))
+        };
+        let spare = width - line.width;
+        let (mut x, extra_space) = match align {
+            Align::Left => (0.0, 0.0),
+            Align::Center => (spare / 2.0, 0.0),
+            Align::Right => (spare, 0.0),
+            Align::Justify if !line.ends_paragraph && line.words.len() > 1 => {
+                (0.0, spare / (line.words.len() - 1) as f32)
+            }
+            Align::Justify => (0.0, 0.0),
+        };
+        for (i, word) in line.words.into_iter().enumerate() {
+            if i > 0 {
+                x += word.space_before + extra_space;
+            }
+            for (fragment, text, piece_width) in word.pieces {
+                runs.push(PlacedRun {
+                              fragment: fragment,
+                              text: text,
+                              x: x,
+                              baseline: y + ascent,
+                          });
+                x += piece_width;
+            }
+        }
+        y += height;
+    }
+    (runs, width, y)
+}
+
+/// Returns how far the given text advances the pen when drawn in
+/// `font` at the given scale.
+fn advance_width(font: &Font, scale: f32, text: &str) -> f32 {
+    match *font {
+        Font::TTFFont {
+            font: ref f,
+            scale: s,
+            ..
+        } => {
+            layout_ttf(f, scaled(s, scale), text, rusttype::point(0.0, 0.0)).1
+        }
+        Font::BitmapFont {
+            ref glyphs,
+            ref kerning,
+            ..
+        } => bitmap_advance(glyphs, kerning, text) as f32 * scale,
+        Font::FontSet { ref fonts } => {
+            fallback_runs(fonts, text)
+                .iter()
+                .map(|&(font, ref run)| advance_width(font, scale, run))
+                .sum()
+        }
+    }
+}
+
+/// Returns how far right the glyphs of the given text reach when drawn
+/// in `font` at the given scale starting at `x`, which can be past where
+/// the pen ends up.  Whitespace doesn't reach anywhere.
+fn ink_right(font: &Font, scale: f32, text: &str, x: f32) -> f32 {
+    match *font {
+        Font::TTFFont {
+            font: ref f,
+            scale: s,
+            ..
+        } => {
+            layout_ttf(f, scaled(s, scale), text, rusttype::point(x, 0.0))
+                .0
+                .iter()
+                .filter_map(|g| g.pixel_bounding_box())
+                .map(|bb| bb.max.x as f32)
+                .fold(x, f32::max)
+        }
+        Font::BitmapFont {
+            ref glyphs,
+            ref kerning,
+            ..
+        } => {
+            let mut pen = x;
+            let mut right = x;
+            let mut previous = None;
+            for c in text.chars() {
+                if let Some(p) = previous {
+                    pen += kerning.get(&(p, c)).cloned().unwrap_or(0) as f32 * scale;
+                }
+                previous = Some(c);
+                if let Some(glyph) = glyphs.get(&c) {
+                    let left = (pen + glyph.x_offset as f32 * scale).round();
+                    right = right.max(left + (glyph.width as f32 * scale).ceil());
+                    pen += glyph.advance as f32 * scale;
+                }
+            }
+            right
+        }
+        Font::FontSet { ref fonts } => {
+            let mut pen = x;
+            let mut right = x;
+            for (font, run) in fallback_runs(fonts, text) {
+                right = right.max(ink_right(font, scale, &run, pen));
+                pen += advance_width(font, scale, &run);
+            }
+            right
+        }
+    }
+}
+
+/// Returns the ascent and line height of `font` at the given scale.
+fn line_metrics(font: &Font, scale: f32) -> (f32, f32) {
+    match *font {
+        Font::TTFFont {
+            font: ref f,
+            scale: s,
+            ..
+        } => {
+            let s = scaled(s, scale);
+            (f.v_metrics(s).ascent, s.y.ceil())
+        }
+        Font::BitmapFont {
+            line_height,
+            base,
+            ..
+        } => (base as f32 * scale, line_height as f32 * scale),
+        Font::FontSet { ref fonts } => {
+            fonts
+                .iter()
+                .map(|f| line_metrics(f, scale))
+                .fold((0.0, 0.0), |(a, h), (a2, h2)| (f32::max(a, a2), f32::max(h, h2)))
+        }
+    }
+}
+
+/// Blends a pixel of the given color over the one at `(x, y)` in an
+/// RGBA buffer `width` pixels wide, if it's inside the buffer.
+fn blend_pixel(buf: &mut [u8], width: usize, height: usize, x: i32, y: i32, color: [f32; 4]) {
+    if x < 0 || y < 0 || x as usize >= width || y as usize >= height || color[3] <= 0.0 {
+        return;
+    }
+    let i = (y as usize * width + x as usize) * 4;
+    let dest_alpha = buf[i + 3] as f32 / 255.0;
+    let alpha = color[3] + dest_alpha * (1.0 - color[3]);
+    for c in 0..3 {
+        let dest = buf[i + c] as f32 / 255.0;
+        let blended = (color[c] * color[3] + dest * dest_alpha * (1.0 - color[3])) / alpha;
+        buf[i + c] = (blended * 255.0).round() as u8;
+    }
+    buf[i + 3] = (alpha * 255.0).round() as u8;
+}
+
+/// Returns how many pixels of room the effects in `options` need on
+/// each side of the text.
+fn effect_padding(options: &TextOptions) -> usize {
+    let outline = options.outline.map_or(0.0, |o| o.width.max(0.0));
+    let shadow = options
+        .shadow
+        .map_or(0.0, |s| s.offset.x.abs().max(s.offset.y.abs()) + s.blur.max(0.0));
+    let sdf = options.sdf.map_or(0.0, |spread| spread.max(1.0));
+    (outline + shadow + sdf).ceil() as usize
+}
+
+/// Spreads coverage out by `radius` pixels in every direction,
+/// with antialiased edges.
+fn dilate(coverage: &[f32], width: usize, height: usize, radius: f32) -> Vec<f32> {
+    let reach = radius.ceil() as i32;
+    let mut result = vec![0.0; coverage.len()];
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let mut value = 0.0f32;
+            for dy in -reach..reach + 1 {
+                for dx in -reach..reach + 1 {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 {
+                        continue;
+                    }
+                    // Both pixels reach half a pixel out from their centers.
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                    let falloff = (radius + 1.0 - distance).max(0.0).min(1.0);
+                    value = value.max(coverage[sy as usize * width + sx as usize] * falloff);
+                }
+            }
+            result[y as usize * width + x as usize] = value;
+        }
+    }
+    result
+}
+
+/// Moves coverage `dx` pixels right and `dy` down; whatever
+/// goes off the edges is lost.
+fn shift(coverage: &[f32], width: usize, height: usize, dx: i32, dy: i32) -> Vec<f32> {
+    let mut result = vec![0.0; coverage.len()];
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let (sx, sy) = (x - dx, y - dy);
+            if sx >= 0 && sy >= 0 && sx < width as i32 && sy < height as i32 {
+                result[y as usize * width + x as usize] = coverage[sy as usize * width +
+                                                                   sx as usize];
+            }
+        }
+    }
+    result
+}
+
+/// Averages each value of coverage with the ones up to `radius`
+/// pixels either side of it, either horizontally or vertically.
+fn box_blur(coverage: &[f32],
+            width: usize,
+            height: usize,
+            radius: usize,
+            horizontal: bool)
+            -> Vec<f32> {
+    let reach = radius as i32;
+    let taps = (radius * 2 + 1) as f32;
+    let mut result = vec![0.0; coverage.len()];
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let mut sum = 0.0;
+            for d in -reach..reach + 1 {
+                let (sx, sy) = if horizontal { (x + d, y) } else { (x, y + d) };
+                if sx >= 0 && sy >= 0 && sx < width as i32 && sy < height as i32 {
+                    sum += coverage[sy as usize * width + sx as usize];
+                }
+            }
+            result[y as usize * width + x as usize] = sum / taps;
+        }
+    }
+    result
+}
+
+/// Blends a solid color over an RGBA buffer, with the given coverage.
+fn paint(buf: &mut [u8], width: usize, height: usize, coverage: &[f32], color: Color) {
+    for (i, &c) in coverage.iter().enumerate() {
+        let pixel = [color.r, color.g, color.b, color.a * c];
+        blend_pixel(buf, width, height, (i % width) as i32, (i / width) as i32, pixel);
+    }
+}
+
+/// Turns an RGBA buffer into a signed distance field: each pixel's
+/// alpha becomes how far it is from the edge of the opaque parts,
+/// mapped so that the edge is at one half and `spread` pixels either
+/// side of it are 0 and 1.  Pixels outside take the color of the
+/// nearest pixel inside, so filtering doesn't darken the edges.
+fn distance_field(pixels: &[u8], width: usize, height: usize, spread: f32) -> Vec<u8> {
+    let reach = spread.ceil() as i32;
+    let inside = |x: i32, y: i32| pixels[(y as usize * width + x as usize) * 4 + 3] >= 128;
+    let mut result = pixels.to_vec();
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let here = inside(x, y);
+            let mut nearest = spread;
+            let mut nearest_pixel = None;
+            for dy in -reach..reach + 1 {
+                for dx in -reach..reach + 1 {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 ||
+                       inside(sx, sy) == here {
+                        continue;
+                    }
+                    // The edge is halfway between the two pixels' centers.
+                    let distance = ((dx * dx + dy * dy) as f32).sqrt() - 0.5;
+                    if distance < nearest {
+                        nearest = distance;
+                        nearest_pixel = Some((sy as usize * width + sx as usize) * 4);
+                    }
+                }
+            }
+            let i = (y as usize * width + x as usize) * 4;
+            let signed = if here {
+                nearest
+            } else {
+                if let Some(j) = nearest_pixel {
+                    result[i..i + 3].copy_from_slice(&pixels[j..j + 3]);
+                }
+                -nearest
+            };
+            let alpha = (0.5 + signed / (2.0 * spread)).max(0.0).min(1.0);
+            result[i + 3] = (alpha * 255.0).round() as u8;
+        }
+    }
+    result
+}
+
+/// Renders the effects in `options` around text rasterized into an
+/// RGBA buffer, into a new buffer `padding` pixels bigger on each side.
+fn apply_effects(pixels: &[u8],
                  width: usize,
                  height: usize,
-                 glyphs_map: &BTreeMap<char, usize>,
-                 glyph_width: usize)
-                 -> GameResult<Text> {
-    let text_length = text.len();
-    let glyph_height = height;
-    let buf_len = text_length * glyph_width * glyph_height * 4;
-    let mut dest_buf = Vec::with_capacity(buf_len);
-    dest_buf.resize(buf_len, 0u8);
-    for (i, c) in text.chars().enumerate() {
-        // println!("Rendering character {}: {}", i, c);
-        let error = GameError::FontError(format!("Character '{}' not in bitmap font!", c));
-        let source_offset = *glyphs_map
-                                 .get(&c)
-                                 .ok_or(error)?;
-        let dest_offset = glyph_width * i;
-        // println!("Blitting {:?} to {:?}", source_offset, dest_offset);
-        blit(&mut dest_buf,
-             (text_length * glyph_width, glyph_height),
-             (dest_offset, 0),
-             bytes,
-             (width, height),
-             (source_offset, 0),
-             (glyph_width, glyph_height),
-             4);
-    }
-    // println!("width {}, height {}", text_length * glyph_width, glyph_height);
-    let image = Image::from_rgba8(context,
-                                  (text_length * glyph_width) as u16,
-                                  glyph_height as u16,
-                                  &dest_buf)?;
-    let text_string = text.to_string();
+                 padding: usize,
+                 options: &TextOptions)
+                 -> Vec<u8> {
+    let new_width = width + padding * 2;
+    let new_height = height + padding * 2;
+    let mut text = vec![0; new_width * new_height * 4];
+    for (y, row) in pixels.chunks(width * 4).enumerate() {
+        let start = ((y + padding) * new_width + padding) * 4;
+        text[start..start + row.len()].copy_from_slice(row);
+    }
+    let coverage: Vec<f32> = text.chunks(4).map(|p| p[3] as f32 / 255.0).collect();
+    let outline = options
+        .outline
+        .map(|o| dilate(&coverage, new_width, new_height, o.width));
+
+    let mut result = vec![0; text.len()];
+    if let Some(shadow) = options.shadow {
+        let (dx, dy) = (shadow.offset.x.round() as i32, shadow.offset.y.round() as i32);
+        let shape = shift(outline.as_ref().unwrap_or(&coverage), new_width, new_height, dx, dy);
+        let radius = shadow.blur.max(0.0).round() as usize;
+        let blurred = box_blur(&shape, new_width, new_height, radius, true);
+        let blurred = box_blur(&blurred, new_width, new_height, radius, false);
+        paint(&mut result, new_width, new_height, &blurred, shadow.color);
+    }
+    if let (Some(o), Some(outline)) = (options.outline, outline) {
+        paint(&mut result, new_width, new_height, &outline, o.color);
+    }
+    for (i, p) in text.chunks(4).enumerate() {
+        let pixel = [p[0] as f32 / 255.0,
+                     p[1] as f32 / 255.0,
+                     p[2] as f32 / 255.0,
+                     p[3] as f32 / 255.0];
+        blend_pixel(&mut result,
+                    new_width,
+                    new_height,
+                    (i % new_width) as i32,
+                    (i / new_width) as i32,
+                    pixel);
+    }
+
+    match options.sdf {
+        Some(spread) => distance_field(&result, new_width, new_height, spread.max(1.0)),
+        None => result,
+    }
+}
 
+/// Draws a run of text into an RGBA buffer with its baseline starting
+/// at `(x, baseline)`.
+#[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+fn rasterize_run(buf: &mut [u8],
+                 buf_width: usize,
+                 buf_height: usize,
+                 font: &Font,
+                 scale: f32,
+                 color: Color,
+                 text: &str,
+                 x: f32,
+                 baseline: f32)
+                 -> GameResult<()> {
+    match *font {
+        Font::TTFFont {
+            font: ref f,
+            scale: s,
+            ..
+        } => {
+            let (glyphs, _) = layout_ttf(f, scaled(s, scale), text, rusttype::point(x, baseline));
+            for g in glyphs {
+                if let Some(bb) = g.pixel_bounding_box() {
+                    g.draw(|gx, gy, v| {
+                        let pixel = [color.r, color.g, color.b, color.a * v];
+                        blend_pixel(buf,
+                                    buf_width,
+                                    buf_height,
+                                    gx as i32 + bb.min.x,
+                                    gy as i32 + bb.min.y,
+                                    pixel);
+                    });
+                }
+            }
+        }
+        Font::BitmapFont {
+            ref bytes,
+            width,
+            ref glyphs,
+            ref kerning,
+            base,
+            ..
+        } => {
+            let top = baseline - base as f32 * scale;
+            let mut pen = x;
+            let mut previous = None;
+            for c in text.chars() {
+                if let Some(p) = previous {
+                    pen += kerning.get(&(p, c)).cloned().unwrap_or(0) as f32 * scale;
+                }
+                previous = Some(c);
+                let error = GameError::FontError(format!("Character '{}' not in bitmap font!", c));
+                let glyph = glyphs.get(&c).ok_or(error)?;
+                let left = (pen + glyph.x_offset as f32 * scale).round() as i32;
+                let glyph_top = (top + glyph.y_offset as f32 * scale).round() as i32;
+                let dest_width = (glyph.width as f32 * scale).ceil() as i32;
+                let dest_height = (glyph.height as f32 * scale).ceil() as i32;
+                for dy in 0..dest_height {
+                    for dx in 0..dest_width {
+                        // Nearest neighbor, which keeps pixel fonts crisp.
+                        let sx = cmp::min((dx as f32 / scale) as usize, glyph.width - 1);
+                        let sy = cmp::min((dy as f32 / scale) as usize, glyph.height - 1);
+                        let si = ((glyph.y + sy) * width + glyph.x + sx) * 4;
+                        let pixel = [bytes[si] as f32 / 255.0 * color.r,
+                                     bytes[si + 1] as f32 / 255.0 * color.g,
+                                     bytes[si + 2] as f32 / 255.0 * color.b,
+                                     bytes[si + 3] as f32 / 255.0 * color.a];
+                        blend_pixel(buf, buf_width, buf_height, left + dx, glyph_top + dy, pixel);
+                    }
+                }
+                pen += glyph.advance as f32 * scale;
+            }
+        }
+        Font::FontSet { ref fonts } => {
+            let mut pen = x;
+            for (font, run) in fallback_runs(fonts, text) {
+                rasterize_run(buf, buf_width, buf_height, font, scale, color, &run, pen, baseline)?;
+                pen += advance_width(font, scale, &run);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The font, scale and color of each fragment, filling in whatever
+/// a fragment leaves out from `font`.
+fn fragment_styles<'a>(fragments: &[TextFragment<'a>],
+                       font: &'a Font)
+                       -> Vec<(&'a Font, f32, Color)> {
+    fragments
+        .iter()
+        .map(|f| (f.font.unwrap_or(font), f.scale.unwrap_or(1.0), f.color.unwrap_or(WHITE)))
+        .collect()
+}
+
+/// Lays out the fragments with the given styles; see `layout()`.  The
+/// width returned takes in the whole of every glyph.
+fn layout_fragments(fragments: &[TextFragment],
+                    styles: &[(&Font, f32, Color)],
+                    font: &Font,
+                    wrap_limit: Option<u32>,
+                    align: Align,
+                    preserve_whitespace: bool)
+                    -> (Vec<PlacedRun>, f32, f32) {
+    let texts: Vec<&str> = fragments.iter().map(|f| f.text.as_str()).collect();
+    let advance = |i: usize, text: &str| advance_width(styles[i].0, styles[i].1, text);
+    let vmetrics = |i: usize| if i < styles.len() {
+        line_metrics(styles[i].0, styles[i].1)
+    } else {
+        line_metrics(font, 1.0)
+    };
+    let (runs, width, height) = layout(&texts,
+                                       wrap_limit.map(|w| w as f32),
+                                       align,
+                                       preserve_whitespace,
+                                       advance,
+                                       vmetrics);
+    // Some glyphs reach past where the pen ends up, like the tail of an
+    // italic 'f'; make room for them too.
+    let width = runs.iter()
+        .map(|run| {
+                 let (font, scale, _) = styles[run.fragment];
+                 ink_right(font, scale, &run.text, run.x)
+             })
+        .fold(width, f32::max);
+    (runs, width, height)
+}
+
+fn render_fragments(context: &mut Context,
+                    fragments: &[TextFragment],
+                    font: &Font,
+                    wrap_limit: Option<u32>,
+                    align: Align,
+                    preserve_whitespace: bool,
+                    options: &TextOptions)
+                    -> GameResult<Text> {
+    let styles = fragment_styles(fragments, font);
+    let (runs, width, height) =
+        layout_fragments(fragments, &styles, font, wrap_limit, align, preserve_whitespace);
+
+    // We can't create a texture of size 0.
+    let width = (width.ceil() as usize).max(1);
+    let height = (height.ceil() as usize).max(1);
+    let padding = effect_padding(options);
+    if width + padding * 2 >= u16::MAX as usize || height + padding * 2 >= u16::MAX as usize {
+        let msg = format!("Text is too big to render: {}x{}", width, height);
+        return Err(GameError::FontError(msg));
+    }
+    let mut pixel_data = vec![0; width * height * 4];
+    for run in &runs {
+        let (font, scale, color) = styles[run.fragment];
+        rasterize_run(&mut pixel_data,
+                      width,
+                      height,
+                      font,
+                      scale,
+                      color,
+                      &run.text,
+                      run.x,
+                      run.baseline)?;
+    }
+
+    let (pixel_data, width, height) = if *options == TextOptions::default() {
+        (pixel_data, width, height)
+    } else {
+        let pixel_data = apply_effects(&pixel_data, width, height, padding, options);
+        (pixel_data, width + padding * 2, height + padding * 2)
+    };
+
+    let mut image = Image::from_rgba8(context, width as u16, height as u16, &pixel_data)?;
+    if options.sdf.is_some() {
+        // Distance fields only work if they're filtered smoothly.
+        image.set_filter(FilterMode::Linear);
+    }
+    let contents = fragments.iter().map(|f| f.text.as_str()).collect();
     Ok(Text {
-           texture: image,
-           contents: text_string,
+           rendered: Rendered::Texture(image),
+           contents: contents,
+           padding: padding as u32,
+           sdf: options.sdf.is_some(),
        })
 }
 
+/// Queues text to be drawn through the glyph cache by the next call to
+/// `draw_queued_text()`, with its top left corner at `dest`.  The text
+/// is laid out like `Text::from_fragments()` does.
+///
+/// Unlike `Text::new()`, this doesn't make a new texture for the text; each
+/// glyph is drawn into a texture shared by all queued text the first
+/// time it's used, and reused after that.  That makes it the way to go
+/// for text that changes every frame, like a score or FPS counter.
+/// It only works for TTF fonts.
+pub fn queue_text(ctx: &mut Context,
+                  fragments: &[TextFragment],
+                  font: &Font,
+                  dest: Point,
+                  wrap_limit: Option<u32>,
+                  align: Align)
+                  -> GameResult<()> {
+    let queue = &mut ctx.gfx_context.glyph_cache.queue;
+    queue_fragments(queue, fragments, font, dest, wrap_limit, align, false)?;
+    Ok(())
+}
+
+/// Lays out the fragments and adds their glyphs to `queue`, returning
+/// the size of the area they cover.  If any of them can't be drawn
+/// through the glyph cache, none of them are queued.
+fn queue_fragments(queue: &mut Vec<QueuedGlyph>,
+                   fragments: &[TextFragment],
+                   font: &Font,
+                   dest: Point,
+                   wrap_limit: Option<u32>,
+                   align: Align,
+                   preserve_whitespace: bool)
+                   -> GameResult<(f32, f32)> {
+    let styles = fragment_styles(fragments, font);
+    let (runs, width, height) =
+        layout_fragments(fragments, &styles, font, wrap_limit, align, preserve_whitespace);
+    let start = queue.len();
+    for run in runs {
+        let (font, scale, color) = styles[run.fragment];
+        let queued = queue_run(queue,
+                               font,
+                               scale,
+                               color,
+                               &run.text,
+                               dest.x + run.x,
+                               dest.y + run.baseline);
+        if let Err(e) = queued {
+            queue.truncate(start);
+            return Err(e);
+        }
+    }
+    Ok((width, height))
+}
+
+/// Adds the glyphs of a run of text to the glyph cache's queue.
+#[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
+fn queue_run(queue: &mut Vec<QueuedGlyph>,
+             font: &Font,
+             scale: f32,
+             color: Color,
+             text: &str,
+             x: f32,
+             baseline: f32)
+             -> GameResult<()> {
+    match *font {
+        Font::TTFFont {
+            font: ref f,
+            scale: s,
+            id,
+            ..
+        } => {
+            let start = rusttype::point(x, baseline);
+            for glyph in layout_ttf(f, scaled(s, scale), text, start).0 {
+                queue.push(QueuedGlyph {
+                               font_id: id,
+                               glyph: glyph,
+                               color: color.into(),
+                           });
+            }
+        }
+        Font::BitmapFont { .. } => {
+            let msg = String::from("Bitmap fonts can't be drawn through the glyph cache; \
+                                    use a Text instead");
+            return Err(GameError::FontError(msg));
+        }
+        Font::FontSet { ref fonts } => {
+            let mut pen = x;
+            for (font, run) in fallback_runs(fonts, text) {
+                queue_run(queue, font, scale, color, &run, pen, baseline)?;
+                pen += advance_width(font, scale, &run);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turns a glyph cache update, which is just coverage, into white RGBA.
+fn coverage_to_rgba(coverage: &[u8]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(coverage.len() * 4);
+    for &c in coverage {
+        rgba.extend_from_slice(&[255, 255, 255, c]);
+    }
+    rgba
+}
+
+/// Draws all the text queued with `queue_text()` since the last call,
+/// and empties the queue.  The `DrawParam` applies to all of it at once,
+/// except for `src`, since the text has no size of its own to take part
+/// of.
+pub fn draw_queued_text(ctx: &mut Context, param: DrawParam) -> GameResult<()> {
+    let queue = mem::replace(&mut ctx.gfx_context.glyph_cache.queue, Vec::new());
+    draw_glyphs(ctx, &queue, param, None, None)
+}
+
+/// Makes the two triangles for a glyph drawn at `screen` out of `uv` in
+/// the glyph cache.  If there's an `area`, only the part of the glyph
+/// inside it is kept, and it's moved so the middle of `area` is at the
+/// origin; `None` means nothing was left.
+fn glyph_vertices(uv: Rect,
+                  screen: Rect,
+                  color: [f32; 4],
+                  area: Option<Rect>)
+                  -> Option<[Vertex; 6]> {
+    let (mut left, mut top) = (screen.x, screen.y);
+    let (mut right, mut bottom) = (screen.x + screen.w, screen.y + screen.h);
+    let (mut shift_x, mut shift_y) = (0.0, 0.0);
+    if let Some(area) = area {
+        left = left.max(area.x);
+        top = top.max(area.y);
+        right = right.min(area.x + area.w);
+        bottom = bottom.min(area.y + area.h);
+        shift_x = -(area.x + area.w / 2.0);
+        shift_y = -(area.y + area.h / 2.0);
+    }
+    if left >= right || top >= bottom {
+        return None;
+    }
+    // Cutting the glyph down cuts its texture coordinates down with it.
+    let u = |x: f32| uv.x + (x - screen.x) / screen.w * uv.w;
+    let v = |y: f32| uv.y + (y - screen.y) / screen.h * uv.h;
+    let corner = |x: f32, y: f32| {
+        Vertex {
+            pos: [x + shift_x, y + shift_y],
+            uv: [u(x), v(y)],
+            color: color,
+        }
+    };
+    let top_left = corner(left, top);
+    let top_right = corner(right, top);
+    let bottom_left = corner(left, bottom);
+    let bottom_right = corner(right, bottom);
+    Some([top_left, top_right, bottom_left, top_right, bottom_right, bottom_left])
+}
+
+/// Draws glyphs out of the glyph cache, putting any that aren't in it
+/// yet in first.  `filter` overrides the filter mode of the cache's
+/// texture for this draw only, and `area` is as for `glyph_vertices()`.
+fn draw_glyphs(ctx: &mut Context,
+               queue: &[QueuedGlyph],
+               param: DrawParam,
+               filter: Option<FilterMode>,
+               area: Option<Rect>)
+               -> GameResult<()> {
+    if queue.is_empty() {
+        return Ok(());
+    }
+
+    // Get all the glyphs into the cache, making it bigger if they don't fit.
+    let mut uploads = Vec::new();
+    loop {
+        uploads.clear();
+        let cached = {
+            let cache = &mut ctx.gfx_context.glyph_cache.cache;
+            for queued in queue {
+                cache.queue_glyph(queued.font_id, queued.glyph.clone());
+            }
+            cache.cache_queued(|rect, data| uploads.push((rect, coverage_to_rgba(data))))
+        };
+        if cached.is_ok() {
+            break;
+        }
+        let size = ctx.gfx_context.glyph_cache.size * 2;
+        if size > MAX_GLYPH_CACHE_SIZE {
+            let msg = String::from("Too much different text queued to fit in the glyph cache");
+            return Err(GameError::FontError(msg));
+        }
+        // Whatever is still queued for later isn't lost by starting over
+        // with a bigger cache.
+        let gfx = &mut ctx.gfx_context;
+        let pending = mem::replace(&mut gfx.glyph_cache.queue, Vec::new());
+        gfx.glyph_cache = GlyphCache::new(&mut gfx.factory, &gfx.default_sampler_info, size)?;
+        gfx.glyph_cache.queue = pending;
+    }
+    let mut image = ctx.gfx_context.glyph_cache.image.clone();
+    if let Some(filter) = filter {
+        image.set_filter(filter);
+    }
+    // The cache is never bigger than MAX_GLYPH_CACHE_SIZE, so these fit.
+    for (rect, rgba) in uploads {
+        image.update_region(ctx,
+                            rect.min.x as u16,
+                            rect.min.y as u16,
+                            rect.width() as u16,
+                            rect.height() as u16,
+                            &rgba)?;
+    }
+
+    // Then make two triangles for each of them.
+    let mut vertices = Vec::with_capacity(queue.len() * 6);
+    for queued in queue {
+        let rects = ctx.gfx_context
+            .glyph_cache
+            .cache
+            .rect_for(queued.font_id, &queued.glyph);
+        if let Ok(Some((uv, screen))) = rects {
+            let uv = Rect::new(uv.min.x, uv.min.y, uv.width(), uv.height());
+            let screen = Rect::new_i32(screen.min.x,
+                                       screen.min.y,
+                                       screen.width(),
+                                       screen.height());
+            if let Some(quad) = glyph_vertices(uv, screen, queued.color, area) {
+                vertices.extend_from_slice(&quad);
+            }
+        }
+    }
+    if vertices.is_empty() {
+        // Nothing but whitespace, or nothing inside `area`.
+        return Ok(());
+    }
+
+    let gfx = &mut ctx.gfx_context;
+    let too_small = gfx.glyph_cache
+        .vertex_buffer
+        .as_ref()
+        .map_or(true, |buffer| buffer.len() < vertices.len());
+    if too_small {
+        let buffer = gfx.factory
+            .create_buffer(vertices.len(),
+                           gfx::buffer::Role::Vertex,
+                           gfx::memory::Usage::Dynamic,
+                           gfx::TRANSFER_DST)?;
+        gfx.glyph_cache.vertex_buffer = Some(buffer);
+    }
+    let buffer = gfx.glyph_cache
+        .vertex_buffer
+        .clone()
+        .expect("Glyph cache has no vertex buffer; should never happen!");
+    gfx.encoder.update_buffer(&buffer, &vertices, 0)?;
+    let slice = gfx::Slice {
+        start: 0,
+        end: vertices.len() as u32,
+        base_vertex: 0,
+        instances: None,
+        buffer: gfx::IndexBuffer::Auto,
+    };
+
+    // The texture coordinates are already in the glyph cache's texture,
+    // so they mustn't be moved again.
+    let param = DrawParam { src: Rect::one(), ..param };
+    gfx.update_rect_properties(param)?;
+    gfx.data.vbuf = buffer;
+    let sampler = gfx.samplers
+        .get_or_insert(image.sampler_info, gfx.factory.as_mut());
+    gfx.data.tex = (image.texture.clone(), sampler);
+    gfx.draw(Some(&slice))
+}
+
 
 impl Text {
-    /// Renders a new `Text` from the given `Font`
+    /// Renders a new `Text` from the given `Font`, on one line with all
+    /// its whitespace kept as it is.  Newlines don't start a new line;
+    /// `Text::new_wrapped()` and `Text::from_fragments()` do that.
+    /// Each `Text` has its own texture; for text that changes often,
+    /// `Text::new_cached()` or `queue_text()` avoid making a new one
+    /// every time.
     pub fn new(context: &mut Context, text: &str, font: &Font) -> GameResult<Text> {
-        match *font {
-            Font::TTFFont {
-                font: ref f,
-                scale,
+        render_fragments(context,
+                         &[text.into()],
+                         font,
+                         None,
+                         Align::Left,
+                         true,
+                         &TextOptions::default())
+    }
+
+    /// Renders a new `Text` from the given `Font`, broken into lines no
+    /// wider than `wrap_limit` pixels at whitespace and newlines, and
+    /// lined up as `align` says.
+    pub fn new_wrapped(context: &mut Context,
+                       text: &str,
+                       font: &Font,
+                       wrap_limit: u32,
+                       align: Align)
+                       -> GameResult<Text> {
+        Text::from_fragments(context, &[text.into()], font, Some(wrap_limit), align)
+    }
+
+    /// Renders a new `Text` out of several `TextFragment`s, each with
+    /// its own style; whatever a fragment doesn't set comes from `font`.
+    /// The text is broken into lines at newlines, and also at whitespace
+    /// to keep lines no wider than `wrap_limit` pixels if there is one.
+    pub fn from_fragments(context: &mut Context,
+                          fragments: &[TextFragment],
+                          font: &Font,
+                          wrap_limit: Option<u32>,
+                          align: Align)
+                          -> GameResult<Text> {
+        render_fragments(context,
+                         fragments,
+                         font,
+                         wrap_limit,
+                         align,
+                         false,
+                         &TextOptions::default())
+    }
+
+    /// Renders a new `Text` like `Text::from_fragments()` does, with an
+    /// outline, shadow or both, or as a signed distance field, as
+    /// `options` says.
+    pub fn new_with_options(context: &mut Context,
+                            fragments: &[TextFragment],
+                            font: &Font,
+                            wrap_limit: Option<u32>,
+                            align: Align,
+                            options: &TextOptions)
+                            -> GameResult<Text> {
+        render_fragments(context, fragments, font, wrap_limit, align, false, options)
+    }
+
+    /// Lays out a new `Text` like `Text::new()` does, but draws it out of
+    /// the glyph cache that `queue_text()` uses instead of rendering it
+    /// into a texture of its own.  Its contents can then be changed with
+    /// `set_contents()` without making a new texture.  It only works for
+    /// TTF fonts.
+    pub fn new_cached(context: &mut Context, text: &str, font: &Font) -> GameResult<Text> {
+        let mut text_obj = Text {
+            rendered: Rendered::Cached {
+                glyphs: Vec::new(),
+                width: 1,
+                height: 1,
+                filter: get_default_filter(context),
+            },
+            contents: String::new(),
+            padding: 0,
+            sdf: false,
+        };
+        text_obj.set_contents(text, font)?;
+        Ok(text_obj)
+    }
+
+    /// Changes the text of a `Text` made with `Text::new_cached()`, laying
+    /// it out again with the given font.  Glyphs that haven't been drawn
+    /// before are added to the glyph cache the next time it's drawn, so
+    /// this never makes a new texture.
+    ///
+    /// Text rendered into a texture of its own can't be changed in place;
+    /// this returns an error for it.
+    pub fn set_contents(&mut self, text: &str, font: &Font) -> GameResult<()> {
+        match self.rendered {
+            Rendered::Cached {
+                ref mut glyphs,
+                ref mut width,
+                ref mut height,
                 ..
-            } => render_ttf(context, text, f, scale),
-            Font::BitmapFont {
-                ref bytes,
-                width,
-                height,
-                glyph_width,
-                ref glyphs,
-            } => render_bitmap(context, text, bytes, width, height, glyphs, glyph_width),
+            } => {
+                let mut new_glyphs = Vec::new();
+                let (w, h) = queue_fragments(&mut new_glyphs,
+                                             &[text.into()],
+                                             font,
+                                             Point::default(),
+                                             None,
+                                             Align::Left,
+                                             true)?;
+                *glyphs = new_glyphs;
+                // Same as a rendered `Text`, which can't be 0 pixels across.
+                *width = (w.ceil() as u32).max(1);
+                *height = (h.ceil() as u32).max(1);
+            }
+            Rendered::Texture(_) => {
+                let msg = String::from("Only text made with Text::new_cached() can have its \
+                                        contents changed");
+                return Err(GameError::FontError(msg));
+            }
         }
+        self.contents = text.to_owned();
+        Ok(())
     }
 
     /// Returns the width of the rendered text, in pixels.
     pub fn width(&self) -> u32 {
-        self.texture.width()
+        match self.rendered {
+            Rendered::Texture(ref texture) => texture.width(),
+            Rendered::Cached { width, .. } => width,
+        }
     }
 
     /// Returns the height of the rendered text, in pixels.
     pub fn height(&self) -> u32 {
-        self.texture.height()
+        match self.rendered {
+            Rendered::Texture(ref texture) => texture.height(),
+            Rendered::Cached { height, .. } => height,
+        }
     }
 
     /// Returns the string that the text represents.
@@ -444,27 +1895,71 @@ impl Text {
         &self.contents
     }
 
+    /// Returns how many pixels of room are left on each side of the text
+    /// for the effects it was rendered with, so that the text itself
+    /// starts this far in from the top left corner.
+    pub fn padding(&self) -> u32 {
+        self.padding
+    }
+
     /// Returns the dimensions of the rendered text.
     pub fn get_dimensions(&self) -> Rect {
-        self.texture.get_dimensions()
+        Rect::new(0.0, 0.0, self.width() as f32, self.height() as f32)
     }
 
 
     /// Get the filter mode for the the rendered text.
     pub fn get_filter(&self) -> FilterMode {
-        self.texture.get_filter()
+        match self.rendered {
+            Rendered::Texture(ref texture) => texture.get_filter(),
+            Rendered::Cached { filter, .. } => filter,
+        }
     }
 
     /// Set the filter mode for the the rendered text.
     pub fn set_filter(&mut self, mode: FilterMode) {
-        self.texture.set_filter(mode);
+        match self.rendered {
+            Rendered::Texture(ref mut texture) => texture.set_filter(mode),
+            Rendered::Cached { ref mut filter, .. } => *filter = mode,
+        }
     }
 }
 
 
 impl Drawable for Text {
     fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
-        draw_ex(ctx, &self.texture, param)
+        let texture = match self.rendered {
+            Rendered::Texture(ref texture) => texture,
+            Rendered::Cached {
+                ref glyphs,
+                width,
+                height,
+                filter,
+            } => {
+                // Placed just like a `Text` with its own texture: the part
+                // of it `src` picks out is centered on `dest`, and offsets
+                // are turned around the same way `Image` does.
+                let (width, height) = (width as f32, height as f32);
+                let area = Rect::new(param.src.x * width,
+                                     param.src.y * height,
+                                     param.src.w * width,
+                                     param.src.h * height);
+                let mut param = param;
+                param.offset.x *= -1.0 * param.scale.x;
+                param.offset.y *= param.scale.y;
+                return draw_glyphs(ctx, glyphs, param, Some(filter), Some(area));
+            }
+        };
+        if !self.sdf {
+            return draw_ex(ctx, texture, param);
+        }
+        // Distance fields need their own shader to turn them back into
+        // text, in place of whichever one is in use.
+        let shader = ctx.gfx_context.current_shader.clone();
+        let previous_shader = shader.replace(SDF_SHADER);
+        let result = draw_ex(ctx, texture, param);
+        shader.set(previous_shader);
+        result
     }
 }
 
@@ -472,8 +1967,8 @@ impl fmt::Debug for Text {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f,
                "<Text: {}x{}, {:p}>",
-               self.texture.width,
-               self.texture.height,
+               self.width(),
+               self.height(),
                &self)
 
     }
@@ -547,6 +2042,271 @@ mod tests {
         assert_eq!(&v, &wrapped_text);
     }
 
+    #[test]
+    fn test_bitmap_grid() {
+        let img = image::RgbaImage::new(8, 4);
+        assert!(bitmap_grid(img.clone(), "abcde", 4, 2).is_err());
+        match bitmap_grid(img, "abcd", 4, 2).unwrap() {
+            Font::BitmapFont { ref glyphs, line_height, .. } => {
+                assert_eq!(line_height, 2);
+                assert_eq!((glyphs[&'b'].x, glyphs[&'b'].y), (4, 0));
+                assert_eq!((glyphs[&'c'].x, glyphs[&'c'].y), (0, 2));
+                assert_eq!(glyphs[&'d'].advance, 4);
+            }
+            _ => panic!("bitmap_grid() didn't make a bitmap font"),
+        }
+    }
+
+    #[test]
+    fn test_bitmap_advance() {
+        let glyph = BitmapGlyph {
+            x: 0,
+            y: 0,
+            width: 5,
+            height: 5,
+            x_offset: 0,
+            y_offset: 0,
+            advance: 6,
+        };
+        let glyphs = vec![('A', glyph), ('V', glyph)].into_iter().collect();
+        let kerning = vec![(('A', 'V'), -2)].into_iter().collect();
+        assert_eq!(bitmap_advance(&glyphs, &kerning, "AV"), 10);
+        assert_eq!(bitmap_advance(&glyphs, &kerning, "VA"), 12);
+        // Characters the font doesn't have take up no room.
+        assert_eq!(bitmap_advance(&glyphs, &kerning, "A?"), 6);
+
+        assert!(glyph_fits(&glyph, 5, 5));
+        assert!(!glyph_fits(&glyph, 4, 5));
+        let huge = BitmapGlyph { x: ::std::usize::MAX, ..glyph };
+        assert!(!glyph_fits(&huge, 5, 5));
+    }
+
+    #[test]
+    fn test_effects() {
+        // One white pixel, with room around it.
+        let mut pixels = vec![0; 3 * 3 * 4];
+        pixels[16..20].copy_from_slice(&[255, 255, 255, 255]);
+        let alpha = |buf: &[u8], width: usize, x: usize, y: usize| buf[(y * width + x) * 4 + 3];
+
+        let options = TextOptions {
+            outline: Some(Outline {
+                              width: 1.0,
+                              color: Color::new(0.0, 0.0, 0.0, 1.0),
+                          }),
+            ..Default::default()
+        };
+        assert_eq!(effect_padding(&options), 1);
+        let result = apply_effects(&pixels, 3, 3, 1, &options);
+        assert_eq!(&result[(2 * 5 + 2) * 4..(2 * 5 + 3) * 4], &[255, 255, 255, 255]);
+        assert_eq!(&result[(2 * 5 + 3) * 4..(2 * 5 + 4) * 4], &[0, 0, 0, 255]);
+        assert!(alpha(&result, 5, 3, 3) > 0 && alpha(&result, 5, 3, 3) < 255);
+        assert_eq!(alpha(&result, 5, 4, 2), 0);
+
+        let options = TextOptions {
+            shadow: Some(Shadow {
+                             offset: Point::new(2.0, 0.0),
+                             color: Color::new(0.0, 0.0, 0.0, 0.5),
+                             blur: 0.0,
+                         }),
+            ..Default::default()
+        };
+        assert_eq!(effect_padding(&options), 2);
+        let result = apply_effects(&pixels, 3, 3, 2, &options);
+        assert_eq!(alpha(&result, 7, 3, 3), 255);
+        assert_eq!(alpha(&result, 7, 5, 3), 128);
+        assert_eq!(alpha(&result, 7, 4, 3), 0);
+
+        let options = TextOptions {
+            sdf: Some(2.0),
+            ..Default::default()
+        };
+        let result = apply_effects(&pixels, 3, 3, 2, &options);
+        assert!(alpha(&result, 7, 3, 3) > 128);
+        assert!(alpha(&result, 7, 4, 3) < 128);
+        assert!(alpha(&result, 7, 4, 3) > alpha(&result, 7, 5, 3));
+        // Outside pixels take the color of the text they're next to.
+        assert_eq!(&result[(3 * 7 + 4) * 4..(3 * 7 + 4) * 4 + 3], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn test_fallback_runs() {
+        let img = image::RgbaImage::new(8, 2);
+        let fonts = vec![bitmap_grid(img.clone(), "ab", 4, 2).unwrap(),
+                         bitmap_grid(img, "bc", 4, 2).unwrap()];
+        let runs = fallback_runs(&fonts, "abcd");
+        let runs: Vec<(bool, &str)> = runs.iter()
+            .map(|&(font, ref run)| (ptr::eq(font, &fonts[0]), run.as_str()))
+            .collect();
+        // Nobody has 'd', so it goes to the first font.
+        assert_eq!(runs, vec![(true, "ab"), (false, "c"), (true, "d")]);
+
+        let set = Font::new_font_set(fonts).unwrap();
+        assert!(set.has_glyph('c'));
+        assert!(!set.has_glyph('d'));
+        assert_eq!(set.get_width("abc"), 12);
+        assert!(Font::new_font_set(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_queue_fragments() {
+        let ttf = Font::default_font().unwrap();
+        let bitmap = bitmap_grid(image::RgbaImage::new(8, 2), "ab", 4, 2).unwrap();
+        let mut queue = Vec::new();
+        let (width, _) = queue_fragments(&mut queue,
+                                         &["ab".into()],
+                                         &ttf,
+                                         Point::default(),
+                                         None,
+                                         Align::Left,
+                                         false)
+            .unwrap();
+        assert_eq!(queue.len(), 2);
+        assert!(width > 0.0);
+
+        // A bitmap font partway through leaves nothing half queued.
+        let fragments = [TextFragment::new("ab"), TextFragment::new("ab").font(&bitmap)];
+        assert!(queue_fragments(&mut queue,
+                                &fragments,
+                                &ttf,
+                                Point::default(),
+                                None,
+                                Align::Left,
+                                false)
+                        .is_err());
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_glyph_vertices() {
+        let uv = Rect::new(0.5, 0.5, 0.25, 0.25);
+        let screen = Rect::new(0.0, 0.0, 40.0, 20.0);
+        let white = [1.0, 1.0, 1.0, 1.0];
+        let positions = |quad: [Vertex; 6]| -> Vec<[f32; 2]> {
+            quad.iter().map(|v| v.pos).collect()
+        };
+
+        // Queued text stays where it was laid out.
+        let quad = glyph_vertices(uv, screen, white, None).unwrap();
+        assert_eq!(quad[0].pos, [0.0, 0.0]);
+        assert_eq!(quad[4].pos, [40.0, 20.0]);
+
+        // A cached `Text` is centered on the origin, like the quad an
+        // `Image` is drawn with.
+        let quad = glyph_vertices(uv, screen, white, Some(screen)).unwrap();
+        assert_eq!(positions(quad),
+                   vec![[-20.0, -10.0], [20.0, -10.0], [-20.0, 10.0], [20.0, -10.0],
+                        [20.0, 10.0], [-20.0, 10.0]]);
+        assert_eq!(quad[0].uv, [0.5, 0.5]);
+        assert_eq!(quad[4].uv, [0.75, 0.75]);
+
+        // Only the part inside `src` is drawn, centered on its own.
+        let right_half = Rect::new(20.0, 0.0, 20.0, 20.0);
+        let quad = glyph_vertices(uv, screen, white, Some(right_half)).unwrap();
+        assert_eq!(quad[0].pos, [-10.0, -10.0]);
+        assert_eq!(quad[4].pos, [10.0, 10.0]);
+        assert_eq!(quad[0].uv, [0.625, 0.5]);
+        let elsewhere = Rect::new(50.0, 0.0, 10.0, 10.0);
+        assert!(glyph_vertices(uv, screen, white, Some(elsewhere)).is_none());
+    }
+
+    // Every character is 10 pixels wide, and lines are 20 high.
+    fn mono_layout(fragments: &[&str],
+                   wrap_limit: Option<f32>,
+                   align: Align)
+                   -> (Vec<PlacedRun>, f32, f32) {
+        layout(fragments,
+               wrap_limit,
+               align,
+               false,
+               |_, text| text.chars().count() as f32 * 10.0,
+               |_| (15.0, 20.0))
+    }
+
+    fn run_positions(runs: &[PlacedRun]) -> Vec<(&str, f32, f32)> {
+        runs.iter().map(|r| (r.text.as_str(), r.x, r.baseline)).collect()
+    }
+
+    #[test]
+    fn test_layout_wrapping() {
+        let text = "one two  three\n\nfour";
+        let (runs, width, height) = mono_layout(&[text], Some(80.0), Align::Left);
+        assert_eq!(run_positions(&runs),
+                   vec![("one", 0.0, 15.0), ("two", 40.0, 15.0), ("three", 0.0, 35.0),
+                        ("four", 0.0, 75.0)]);
+        assert_eq!((width, height), (80.0, 80.0));
+
+        // Without a limit, only newlines break lines.
+        let (runs, width, _) = mono_layout(&["one two"], None, Align::Left);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(width, 70.0);
+    }
+
+    #[test]
+    fn test_layout_preserving_whitespace() {
+        let mono_line = |fragments: &[&str]| {
+            layout(fragments,
+                   None,
+                   Align::Left,
+                   true,
+                   |_, text| text.chars().count() as f32 * 10.0,
+                   |_| (15.0, 20.0))
+        };
+        // Both spaces take up room.
+        let (runs, width, _) = mono_line(&["a  b"]);
+        assert_eq!(run_positions(&runs), vec![("a  b", 0.0, 15.0)]);
+        assert_eq!(width, 40.0);
+
+        // So does whitespace at either end, and newlines stay on the line.
+        let (runs, width, height) = mono_line(&[" a\nb "]);
+        assert_eq!(run_positions(&runs), vec![(" a\nb ", 0.0, 15.0)]);
+        assert_eq!((width, height), (50.0, 20.0));
+
+        let (runs, width, _) = mono_line(&["a ", "", " b"]);
+        assert_eq!(run_positions(&runs), vec![("a ", 0.0, 15.0), (" b", 20.0, 15.0)]);
+        assert_eq!(width, 40.0);
+    }
+
+    #[test]
+    fn test_layout_align() {
+        let (runs, _, _) = mono_layout(&["ab cd\nef"], Some(100.0), Align::Center);
+        assert_eq!(run_positions(&runs),
+                   vec![("ab", 25.0, 15.0), ("cd", 55.0, 15.0), ("ef", 40.0, 35.0)]);
+
+        let (runs, _, _) = mono_layout(&["ab cd"], Some(100.0), Align::Right);
+        assert_eq!(runs[0].x, 50.0);
+
+        // Justified lines fill the width, except the last one.
+        let (runs, _, _) = mono_layout(&["ab cd ef gh"], Some(100.0), Align::Justify);
+        assert_eq!(run_positions(&runs),
+                   vec![("ab", 0.0, 15.0), ("cd", 40.0, 15.0), ("ef", 80.0, 15.0),
+                        ("gh", 0.0, 35.0)]);
+    }
+
+    #[test]
+    fn test_layout_fragments() {
+        // A word split across fragments stays together.
+        let (runs, _, _) = mono_layout(&["ab", "cd ef"], Some(40.0), Align::Left);
+        assert_eq!(runs,
+                   vec![PlacedRun {
+                            fragment: 0,
+                            text: String::from("ab"),
+                            x: 0.0,
+                            baseline: 15.0,
+                        },
+                        PlacedRun {
+                            fragment: 1,
+                            text: String::from("cd"),
+                            x: 20.0,
+                            baseline: 15.0,
+                        },
+                        PlacedRun {
+                            fragment: 1,
+                            text: String::from("ef"),
+                            x: 0.0,
+                            baseline: 35.0,
+                        }]);
+    }
+
     // We sadly can't have this test in the general case because it needs to create a Context,
     // which creates a window, which fails on a headless server like our CI systems.  :/
     //#[test]
@@ -1,3 +1,7 @@
+use std::ops::Mul;
+
+use gfx::state::{Blend, BlendChannel, BlendValue, Comparison, Equation, Factor};
+
 /// A simple 2D point.
 #[derive(Copy, Clone, PartialEq, Debug, Default)]
 pub struct Point {
@@ -103,6 +107,108 @@ impl From<Rect> for [f32; 4] {
 }
 
 
+/// A 2D transformation, such as a translation, rotation or scale,
+/// or any combination of them.
+///
+/// Transformations can be combined by multiplying them; `a * b` is
+/// a transformation that does `b` and then `a`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+    // Row-major, so the translation is in the last column.
+    matrix: [[f32; 4]; 4],
+}
+
+impl Transform {
+    /// Creates a `Transform` that does nothing.
+    pub fn identity() -> Self {
+        Transform::from_matrix4([[1.0, 0.0, 0.0, 0.0],
+                                 [0.0, 1.0, 0.0, 0.0],
+                                 [0.0, 0.0, 1.0, 0.0],
+                                 [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    /// Creates a `Transform` that moves things by the given amount.
+    pub fn translate(x: f32, y: f32) -> Self {
+        Transform::from_matrix3([[1.0, 0.0, x], [0.0, 1.0, y], [0.0, 0.0, 1.0]])
+    }
+
+    /// Creates a `Transform` that rotates things around the origin
+    /// by the given angle in radians, in the same direction as
+    /// `DrawParam`'s `rotation`.
+    pub fn rotate(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Transform::from_matrix3([[cos, -sin, 0.0], [sin, cos, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    /// Creates a `Transform` that scales things away from the origin
+    /// by the given factors.
+    pub fn scale(x: f32, y: f32) -> Self {
+        Transform::from_matrix3([[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    /// Creates a `Transform` from a row-major 3x3 matrix
+    /// of homogeneous 2D coordinates.
+    pub fn from_matrix3(m: [[f32; 3]; 3]) -> Self {
+        Transform::from_matrix4([[m[0][0], m[0][1], 0.0, m[0][2]],
+                                 [m[1][0], m[1][1], 0.0, m[1][2]],
+                                 [0.0, 0.0, 1.0, 0.0],
+                                 [m[2][0], m[2][1], 0.0, m[2][2]]])
+    }
+
+    /// Creates a `Transform` from a row-major 4x4 matrix.
+    pub fn from_matrix4(m: [[f32; 4]; 4]) -> Self {
+        Transform { matrix: m }
+    }
+
+    /// Returns the `Transform` as a row-major 4x4 matrix.
+    pub fn to_matrix4(&self) -> [[f32; 4]; 4] {
+        self.matrix
+    }
+
+    /// Applies the `Transform` to a point.
+    pub fn transform_point(&self, p: Point) -> Point {
+        let m = &self.matrix;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][3];
+        Point::new(x / w, y / w)
+    }
+
+    /// Returns the `Transform` that undoes this one, or `None` if there
+    /// isn't one, for instance because it scales things down to nothing.
+    ///
+    /// This only considers the 2D part of the transformation,
+    /// which is all a `Transform` made from 2D operations has anyway.
+    pub fn inverse(&self) -> Option<Self> {
+        let m = &self.matrix;
+        let (a, b, tx) = (m[0][0], m[0][1], m[0][3]);
+        let (c, d, ty) = (m[1][0], m[1][1], m[1][3]);
+        let det = a * d - b * c;
+        if det == 0.0 {
+            return None;
+        }
+        let (ia, ib) = (d / det, -b / det);
+        let (ic, id) = (-c / det, a / det);
+        Some(Transform::from_matrix3([[ia, ib, -(ia * tx + ib * ty)],
+                                      [ic, id, -(ic * tx + id * ty)],
+                                      [0.0, 0.0, 1.0]]))
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform::from_matrix4(super::matrix_mul(&self.matrix, &rhs.matrix))
+    }
+}
+
 /// A RGBA color.
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Color {
@@ -204,6 +310,94 @@ impl From<Color> for u32 {
     }
 }
 
+/// A color that changes smoothly across a shape.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gradient {
+    /// Changes along the line from `start` to `end`, and stays the
+    /// same at right angles to it.
+    Linear {
+        start: Point,
+        end: Point,
+        start_color: Color,
+        end_color: Color,
+    },
+    /// Changes going outwards from `center`, reaching
+    /// `outer_color` at `radius` away from it.
+    Radial {
+        center: Point,
+        radius: f32,
+        inner_color: Color,
+        outer_color: Color,
+    },
+}
+
+impl Gradient {
+    /// Returns the color of the gradient at the given point.
+    pub fn color_at(&self, point: Point) -> Color {
+        match *self {
+            Gradient::Linear { start, end, start_color, end_color } => {
+                let (dx, dy) = (end.x - start.x, end.y - start.y);
+                let length_squared = dx * dx + dy * dy;
+                let t = if length_squared == 0.0 {
+                    0.0
+                } else {
+                    ((point.x - start.x) * dx + (point.y - start.y) * dy) / length_squared
+                };
+                lerp_color(start_color, end_color, t)
+            }
+            Gradient::Radial { center, radius, inner_color, outer_color } => {
+                let (dx, dy) = (point.x - center.x, point.y - center.y);
+                let t = if radius == 0.0 {
+                    1.0
+                } else {
+                    (dx * dx + dy * dy).sqrt() / radius
+                };
+                lerp_color(inner_color, outer_color, t)
+            }
+        }
+    }
+}
+
+/// Blends from color `a` to `b` as `t` goes from 0 to 1, clamping `t`.
+fn lerp_color(a: Color, b: Color, t: f32) -> Color {
+    let t = t.max(0.0).min(1.0);
+    Color::new(a.r + (b.r - a.r) * t,
+               a.g + (b.g - a.g) * t,
+               a.b + (b.b - a.b) * t,
+               a.a + (b.a - a.a) * t)
+}
+
+/// What to color a shape with: either a single color or a gradient.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Paint {
+    /// The same color everywhere.
+    Solid(Color),
+    /// A color that changes across the shape; see `Gradient`.
+    Gradient(Gradient),
+}
+
+impl Paint {
+    /// Returns the color of the paint at the given point.
+    pub fn color_at(&self, point: Point) -> Color {
+        match *self {
+            Paint::Solid(color) => color,
+            Paint::Gradient(ref gradient) => gradient.color_at(point),
+        }
+    }
+}
+
+impl From<Color> for Paint {
+    fn from(color: Color) -> Self {
+        Paint::Solid(color)
+    }
+}
+
+impl From<Gradient> for Paint {
+    fn from(gradient: Gradient) -> Self {
+        Paint::Gradient(gradient)
+    }
+}
+
 /// Specifies whether a shape should be drawn
 /// filled or as an outline.
 #[derive(Debug, Copy, Clone)]
@@ -212,11 +406,104 @@ pub enum DrawMode {
     Fill,
 }
 
+/// Specifies which shape an arc is drawn as.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArcMode {
+    /// A slice of pie, with straight lines to the center.
+    Pie,
+    /// Just the curve; filling it joins its ends with a straight line.
+    Open,
+    /// The curve with its ends joined by a straight line.
+    Closed,
+}
+
+/// Specifies how the corners between segments of a line are drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineJoin {
+    /// A sharp corner, cut off past the `miter_limit`.
+    Miter,
+    /// A rounded corner.
+    Round,
+    /// A corner cut off straight across.
+    Bevel,
+}
+
+/// Specifies how the ends of a line are drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineCap {
+    /// The line stops square at its end point.
+    Butt,
+    /// The line ends in a half circle around its end point.
+    Round,
+    /// The line ends in a square half its width past its end point.
+    Square,
+}
+
+/// How lines and the outlines of stroked shapes are drawn.
+///
+/// A plain `f32` converts into the default options with that width,
+/// so anything that takes `Into<StrokeOptions>` can just be given a width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeOptions {
+    /// The width of the line.
+    pub width: f32,
+    /// How corners are drawn.
+    pub join: LineJoin,
+    /// How the ends of the line, and of each dash, are drawn.
+    pub cap: LineCap,
+    /// How far, relative to the line width, a `Miter` join may stick
+    /// out before it is cut off.
+    pub miter_limit: f32,
+    /// Alternating lengths of dashes and the gaps between them, starting
+    /// with a dash.  Empty for a solid line.  A zero-length dash is a
+    /// dot with a `Round` or `Square` cap, and nothing with `Butt`.
+    pub dashes: Vec<f32>,
+}
+
+impl StrokeOptions {
+    /// Returns the default options with the given width.
+    pub fn with_width(width: f32) -> Self {
+        StrokeOptions {
+            width: width,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for StrokeOptions {
+    fn default() -> Self {
+        StrokeOptions {
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+            dashes: Vec::new(),
+        }
+    }
+}
+
+impl From<f32> for StrokeOptions {
+    fn from(width: f32) -> Self {
+        StrokeOptions::with_width(width)
+    }
+}
+
 /// Specifies what blending method to use when scaling up/down images.
-#[derive(Debug, Copy, Clone)]
+///
+/// `Trilinear` and `Anisotropic` blend between mipmap levels when scaling
+/// images down, so only make a difference for images created with
+/// mipmaps, such as by `Image::new_mipmapped()`.  Otherwise they act
+/// like `Linear`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FilterMode {
     Linear,
     Nearest,
+    /// Linear filtering within and between mipmap levels.
+    Trilinear,
+    /// Trilinear filtering that also keeps images sharp when seen at an
+    /// angle or squashed, sampling up to the given number of times
+    /// (usually up to 16).
+    Anisotropic(u8),
 }
 
 use gfx::texture;
@@ -225,8 +512,10 @@ use gfx::texture::FilterMethod;
 impl From<FilterMethod> for FilterMode {
     fn from(f: FilterMethod) -> Self {
         match f {
-            FilterMethod::Scale => FilterMode::Nearest,
-            _other => FilterMode::Linear,
+            FilterMethod::Scale | FilterMethod::Mipmap => FilterMode::Nearest,
+            FilterMethod::Bilinear => FilterMode::Linear,
+            FilterMethod::Trilinear => FilterMode::Trilinear,
+            FilterMethod::Anisotropic(level) => FilterMode::Anisotropic(level),
         }
     }
 }
@@ -237,6 +526,8 @@ impl From<FilterMode> for FilterMethod {
         match f {
             FilterMode::Nearest => FilterMethod::Scale,
             FilterMode::Linear => FilterMethod::Bilinear,
+            FilterMode::Trilinear => FilterMethod::Trilinear,
+            FilterMode::Anisotropic(level) => FilterMethod::Anisotropic(level),
         }
     }
 }
@@ -244,6 +535,132 @@ impl From<FilterMode> for FilterMethod {
 /// Specifies how to wrap textures.
 pub type WrapMode = texture::WrapMode;
 
+/// Specifies how colors being drawn are combined with
+/// what has already been drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// The usual alpha blending; the default.
+    Alpha,
+    /// Adds the color being drawn to what's underneath,
+    /// scaled by its alpha.
+    Add,
+    /// Subtracts the color being drawn from what's underneath,
+    /// scaled by its alpha.
+    Subtract,
+    /// Multiplies the color being drawn with what's underneath.
+    Multiply,
+    /// Replaces what's underneath entirely, alpha and all.
+    Replace,
+    /// Alpha blending for images whose colors have already
+    /// been multiplied by their alpha.
+    Premultiplied,
+    /// Takes the lighter of the color being drawn and what's underneath.
+    Lighten,
+    /// Takes the darker of the color being drawn and what's underneath.
+    Darken,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Alpha
+    }
+}
+
+impl From<BlendMode> for Blend {
+    fn from(mode: BlendMode) -> Self {
+        let channel = |equation, source, destination| {
+            BlendChannel {
+                equation: equation,
+                source: source,
+                destination: destination,
+            }
+        };
+        let alpha_channel = channel(Equation::Add,
+                                    Factor::One,
+                                    Factor::OneMinus(BlendValue::SourceAlpha));
+        let (color, alpha) = match mode {
+            // Same as gfx::preset::blend::ALPHA, which is what we always used to use.
+            BlendMode::Alpha => {
+                (channel(Equation::Add,
+                         Factor::ZeroPlus(BlendValue::SourceAlpha),
+                         Factor::OneMinus(BlendValue::SourceAlpha)),
+                 channel(Equation::Add, Factor::One, Factor::One))
+            }
+            BlendMode::Add => {
+                (channel(Equation::Add, Factor::ZeroPlus(BlendValue::SourceAlpha), Factor::One),
+                 channel(Equation::Add, Factor::Zero, Factor::One))
+            }
+            BlendMode::Subtract => {
+                (channel(Equation::RevSub,
+                         Factor::ZeroPlus(BlendValue::SourceAlpha),
+                         Factor::One),
+                 channel(Equation::Add, Factor::Zero, Factor::One))
+            }
+            BlendMode::Multiply => {
+                (channel(Equation::Add, Factor::ZeroPlus(BlendValue::DestColor), Factor::Zero),
+                 channel(Equation::Add, Factor::ZeroPlus(BlendValue::DestAlpha), Factor::Zero))
+            }
+            BlendMode::Replace => {
+                (channel(Equation::Add, Factor::One, Factor::Zero),
+                 channel(Equation::Add, Factor::One, Factor::Zero))
+            }
+            BlendMode::Premultiplied => {
+                (channel(Equation::Add,
+                         Factor::One,
+                         Factor::OneMinus(BlendValue::SourceAlpha)),
+                 alpha_channel)
+            }
+            // The factors are ignored by the min and max equations.
+            BlendMode::Lighten => (channel(Equation::Max, Factor::One, Factor::One), alpha_channel),
+            BlendMode::Darken => (channel(Equation::Min, Factor::One, Factor::One), alpha_channel),
+        };
+        Blend {
+            color: color,
+            alpha: alpha,
+        }
+    }
+}
+
+/// Specifies how a value stored in the stencil buffer is compared
+/// with a given value, for stencil tests.  Each mode passes when the
+/// stored value is, for instance, `Greater` than the given one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CompareMode {
+    /// Always passes.
+    Always,
+    /// Never passes.
+    Never,
+    /// Passes if the stored value equals the given one.
+    Equal,
+    /// Passes if the stored value does not equal the given one.
+    NotEqual,
+    /// Passes if the stored value is less than the given one.
+    Less,
+    /// Passes if the stored value is less than or equal to the given one.
+    LessOrEqual,
+    /// Passes if the stored value is greater than the given one.
+    Greater,
+    /// Passes if the stored value is greater than or equal to the given one.
+    GreaterOrEqual,
+}
+
+impl From<CompareMode> for Comparison {
+    fn from(mode: CompareMode) -> Self {
+        // OpenGL compares the reference value against the stored one,
+        // i.e. the other way around, so the inequalities get swapped.
+        match mode {
+            CompareMode::Always => Comparison::Always,
+            CompareMode::Never => Comparison::Never,
+            CompareMode::Equal => Comparison::Equal,
+            CompareMode::NotEqual => Comparison::NotEqual,
+            CompareMode::Less => Comparison::Greater,
+            CompareMode::LessOrEqual => Comparison::GreaterEqual,
+            CompareMode::Greater => Comparison::Less,
+            CompareMode::GreaterOrEqual => Comparison::LessEqual,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,6 +685,73 @@ mod tests {
         assert_eq!(b2, 0x000000FF);
     }
 
+    #[test]
+    fn test_blend_mode_conversions() {
+        let alpha: Blend = BlendMode::default().into();
+        assert_eq!(alpha, ::gfx::preset::blend::ALPHA);
+    }
+
+    #[test]
+    fn test_gradients() {
+        let linear = Gradient::Linear {
+            start: Point::new(0.0, 0.0),
+            end: Point::new(10.0, 0.0),
+            start_color: BLACK,
+            end_color: WHITE,
+        };
+        assert_eq!(linear.color_at(Point::new(-5.0, 3.0)), BLACK);
+        assert_eq!(linear.color_at(Point::new(5.0, 100.0)),
+                   Color::new(0.5, 0.5, 0.5, 1.0));
+        assert_eq!(linear.color_at(Point::new(20.0, 0.0)), WHITE);
+
+        let radial = Gradient::Radial {
+            center: Point::new(0.0, 0.0),
+            radius: 10.0,
+            inner_color: WHITE,
+            outer_color: BLACK,
+        };
+        assert_eq!(radial.color_at(Point::new(0.0, 0.0)), WHITE);
+        assert_eq!(radial.color_at(Point::new(6.0, 8.0)), BLACK);
+        assert_eq!(Paint::from(WHITE).color_at(Point::new(1.0, 2.0)), WHITE);
+    }
+
+    #[test]
+    fn test_filter_mode_conversions() {
+        let modes = [FilterMode::Nearest,
+                     FilterMode::Linear,
+                     FilterMode::Trilinear,
+                     FilterMode::Anisotropic(8)];
+        for &mode in &modes {
+            let method: FilterMethod = mode.into();
+            assert_eq!(FilterMode::from(method), mode);
+        }
+    }
+
+    #[test]
+    fn test_compare_mode_conversions() {
+        let greater: Comparison = CompareMode::Greater.into();
+        assert_eq!(greater, Comparison::Less);
+        let equal: Comparison = CompareMode::Equal.into();
+        assert_eq!(equal, Comparison::Equal);
+    }
+
+    #[test]
+    fn test_transforms() {
+        let p = Point::new(2.0, 3.0);
+        assert_eq!(Transform::identity().transform_point(p), p);
+        assert_eq!(Transform::translate(1.0, -1.0).transform_point(p),
+                   Point::new(3.0, 2.0));
+        assert_eq!(Transform::scale(2.0, 0.5).transform_point(p),
+                   Point::new(4.0, 1.5));
+
+        let t = Transform::translate(10.0, 0.0) * Transform::scale(2.0, 2.0);
+        assert_eq!(t.transform_point(p), Point::new(14.0, 6.0));
+        let back = t.inverse().unwrap().transform_point(Point::new(14.0, 6.0));
+        assert_eq!(back, p);
+
+        assert_eq!(Transform::scale(0.0, 1.0).inverse(), None);
+    }
+
     #[test]
     fn test_rect_scaling() {
         let r1 = Rect::new(0.0, 0.0, 128.0, 128.0);
@@ -0,0 +1,123 @@
+//! The state of the keyboard, which `event::run()` keeps up to date.
+//!
+//! "Just pressed" and "just released" mean since the last frame, so
+//! they're true for the one call of `EventHandler::update()` after a
+//! key goes down or up.
+
+use std::collections::HashSet;
+
+pub use sdl2::keyboard::Keycode;
+pub use sdl2::keyboard::Mod;
+
+use context::Context;
+
+/// Keeps track of which keys are held down.
+#[derive(Debug)]
+pub struct KeyboardContext {
+    pressed_keys: HashSet<Keycode>,
+    just_pressed: HashSet<Keycode>,
+    just_released: HashSet<Keycode>,
+    active_mods: Mod,
+}
+
+impl KeyboardContext {
+    /// Creates a new `KeyboardContext` with no keys held down.
+    pub fn new() -> KeyboardContext {
+        KeyboardContext {
+            pressed_keys: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+            active_mods: Mod::empty(),
+        }
+    }
+
+    /// Records that a key went down or up, and which modifiers
+    /// were active when it did.
+    ///
+    /// It's usually not necessary to call this function yourself,
+    /// `event::run()` will do it for you.
+    pub fn set_key(&mut self, key: Keycode, pressed: bool, mods: Mod) {
+        if pressed {
+            // Key repeats don't count as being pressed again.
+            if self.pressed_keys.insert(key) {
+                self.just_pressed.insert(key);
+            }
+        } else if self.pressed_keys.remove(&key) {
+            self.just_released.insert(key);
+        }
+        self.active_mods = mods;
+    }
+
+    /// Forgets which keys were just pressed or released, as a new
+    /// frame starts.
+    ///
+    /// It's usually not necessary to call this function yourself,
+    /// `event::run()` will do it for you.
+    pub fn tick(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+impl Default for KeyboardContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns whether the given key is held down.
+pub fn is_key_pressed(ctx: &Context, key: Keycode) -> bool {
+    ctx.keyboard_context.pressed_keys.contains(&key)
+}
+
+/// Returns whether the given key went down since the last frame.
+pub fn is_key_just_pressed(ctx: &Context, key: Keycode) -> bool {
+    ctx.keyboard_context.just_pressed.contains(&key)
+}
+
+/// Returns whether the given key came up since the last frame.
+pub fn is_key_just_released(ctx: &Context, key: Keycode) -> bool {
+    ctx.keyboard_context.just_released.contains(&key)
+}
+
+/// Returns all the keys that are held down.
+pub fn pressed_keys(ctx: &Context) -> &HashSet<Keycode> {
+    &ctx.keyboard_context.pressed_keys
+}
+
+/// Returns whether any of the given modifiers, such as
+/// `LSHIFTMOD | RSHIFTMOD` for either shift key, are active.
+pub fn is_mod_active(ctx: &Context, keymod: Mod) -> bool {
+    ctx.keyboard_context.active_mods.intersects(keymod)
+}
+
+/// Returns all the modifiers that are active.
+pub fn active_mods(ctx: &Context) -> Mod {
+    ctx.keyboard_context.active_mods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_edges() {
+        let mut keyboard = KeyboardContext::new();
+        keyboard.set_key(Keycode::A, true, Mod::empty());
+        assert!(keyboard.pressed_keys.contains(&Keycode::A));
+        assert!(keyboard.just_pressed.contains(&Keycode::A));
+
+        // Held keys repeat, but that isn't a new press.
+        keyboard.tick();
+        keyboard.set_key(Keycode::A, true, Mod::empty());
+        assert!(keyboard.pressed_keys.contains(&Keycode::A));
+        assert!(!keyboard.just_pressed.contains(&Keycode::A));
+
+        keyboard.tick();
+        keyboard.set_key(Keycode::A, false, Mod::empty());
+        assert!(keyboard.pressed_keys.is_empty());
+        assert!(keyboard.just_released.contains(&Keycode::A));
+        keyboard.tick();
+        assert!(keyboard.just_released.is_empty());
+    }
+}
@@ -1,10 +1,18 @@
+//! The `input` module lets you check the state of the keyboard, mouse
+//! and gamepads at any time, rather than just when events arrive.
+
 pub use sdl2::controller::GameController;
 use sdl2::GameControllerSubsystem;
 use sdl2::Sdl;
+use sdl2::rect;
 use std::collections::HashMap;
 
 use context::Context;
 use error::GameResult;
+use graphics::Rect;
+
+pub mod keyboard;
+pub mod mouse;
 
 
 // before we can use gamepads (or joysticks) we need to "open" them
@@ -41,3 +49,27 @@ impl GamepadContext {
 pub fn get_gamepad(ctx: &Context, instance_id: i32) -> Option<&GameController> {
     ctx.gamepad_context.gamepads.get(&instance_id)
 }
+
+/// Turns on text input, so that typing calls `text_input_event()` and
+/// `text_editing_event()` on the `EventHandler`, and brings up the
+/// on-screen keyboard or input method window if the platform has one.
+/// SDL turns it on by itself at startup on most desktop platforms.
+pub fn start_text_input(ctx: &mut Context) {
+    ctx.gfx_context.get_window().subsystem().text_input().start();
+}
+
+/// Turns off text input, hiding any on-screen keyboard.
+pub fn stop_text_input(ctx: &mut Context) {
+    ctx.gfx_context.get_window().subsystem().text_input().stop();
+}
+
+/// Tells the input method where the text being typed is, in window
+/// pixels, so that it can put its candidate list next to it rather
+/// than over it.
+pub fn set_text_input_rect(ctx: &mut Context, rect: Rect) {
+    let rect = rect::Rect::new(rect.x as i32,
+                               rect.y as i32,
+                               rect.w.max(0.0) as u32,
+                               rect.h.max(0.0) as u32);
+    ctx.gfx_context.get_window().subsystem().text_input().set_rect(&rect);
+}
@@ -0,0 +1,131 @@
+//! The state of the mouse, which `event::run()` keeps up to date.
+//!
+//! Positions are in window pixels, like the ones mouse events get.
+//! "Just pressed" and "just released" mean since the last frame, as
+//! in the `keyboard` module.
+
+use std::collections::HashSet;
+
+pub use sdl2::mouse::MouseButton;
+
+use context::Context;
+use graphics::Point;
+
+/// Keeps track of where the mouse is and which buttons are held down.
+#[derive(Debug)]
+pub struct MouseContext {
+    position: Point,
+    delta: Point,
+    pressed_buttons: HashSet<MouseButton>,
+    just_pressed: HashSet<MouseButton>,
+    just_released: HashSet<MouseButton>,
+}
+
+impl MouseContext {
+    /// Creates a new `MouseContext` with the mouse at the origin
+    /// and no buttons held down.
+    pub fn new() -> MouseContext {
+        MouseContext {
+            position: Point::default(),
+            delta: Point::default(),
+            pressed_buttons: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+
+    /// Records that the mouse moved to `(x, y)`, having moved
+    /// `(xrel, yrel)` to get there.
+    ///
+    /// It's usually not necessary to call this function yourself,
+    /// `event::run()` will do it for you.
+    pub fn set_position(&mut self, x: i32, y: i32, xrel: i32, yrel: i32) {
+        self.position = Point::new(x as f32, y as f32);
+        self.delta.x += xrel as f32;
+        self.delta.y += yrel as f32;
+    }
+
+    /// Records that a button went down or up with the mouse at `(x, y)`.
+    ///
+    /// It's usually not necessary to call this function yourself,
+    /// `event::run()` will do it for you.
+    pub fn set_button(&mut self, button: MouseButton, pressed: bool, x: i32, y: i32) {
+        self.position = Point::new(x as f32, y as f32);
+        if pressed {
+            if self.pressed_buttons.insert(button) {
+                self.just_pressed.insert(button);
+            }
+        } else if self.pressed_buttons.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    /// Forgets how far the mouse has moved and which buttons were
+    /// just pressed or released, as a new frame starts.
+    ///
+    /// It's usually not necessary to call this function yourself,
+    /// `event::run()` will do it for you.
+    pub fn tick(&mut self) {
+        self.delta = Point::default();
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+impl Default for MouseContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns where the mouse is.
+pub fn position(ctx: &Context) -> Point {
+    ctx.mouse_context.position
+}
+
+/// Returns how far the mouse has moved since the last frame.
+pub fn delta(ctx: &Context) -> Point {
+    ctx.mouse_context.delta
+}
+
+/// Returns whether the given button is held down.
+pub fn is_button_pressed(ctx: &Context, button: MouseButton) -> bool {
+    ctx.mouse_context.pressed_buttons.contains(&button)
+}
+
+/// Returns whether the given button went down since the last frame.
+pub fn is_button_just_pressed(ctx: &Context, button: MouseButton) -> bool {
+    ctx.mouse_context.just_pressed.contains(&button)
+}
+
+/// Returns whether the given button came up since the last frame.
+pub fn is_button_just_released(ctx: &Context, button: MouseButton) -> bool {
+    ctx.mouse_context.just_released.contains(&button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_state() {
+        let mut mouse = MouseContext::new();
+        mouse.set_position(10, 20, 10, 20);
+        mouse.set_position(15, 18, 5, -2);
+        assert_eq!(mouse.position, Point::new(15.0, 18.0));
+        assert_eq!(mouse.delta, Point::new(15.0, 18.0));
+
+        mouse.set_button(MouseButton::Left, true, 16, 18);
+        assert_eq!(mouse.position, Point::new(16.0, 18.0));
+        assert!(mouse.just_pressed.contains(&MouseButton::Left));
+
+        mouse.tick();
+        assert_eq!(mouse.delta, Point::default());
+        assert!(mouse.pressed_buttons.contains(&MouseButton::Left));
+        assert!(mouse.just_pressed.is_empty());
+
+        mouse.set_button(MouseButton::Left, false, 16, 18);
+        assert!(mouse.pressed_buttons.is_empty());
+        assert!(mouse.just_released.contains(&MouseButton::Left));
+    }
+}